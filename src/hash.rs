@@ -3,10 +3,11 @@ extern crate byteorder;
 
 use self::crypto::sha3::Sha3;
 use self::crypto::digest::Digest;
+use std::cell::Cell;
 
 
 /// Stores one of the supported hash values.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Hash {
     None,
     Sha3([u8; 32])
@@ -75,6 +76,31 @@ fn bytes_to_string(bytes: &[u8]) -> String {
 
 
 impl Hash {
+    /// Returns the hash which represents no value.
+    pub fn none() -> Hash {
+        Hash::None
+    }
+
+    /// Returns true if the hash represents no value.
+    pub fn is_none(&self) -> bool {
+        *self == Hash::None
+    }
+
+    /// Returns true if the hash contains an actual hash value.
+    pub fn is_some(&self) -> bool {
+        !self.is_none()
+    }
+
+    /// Number of bytes of the hash value, 0 for None.
+    ///
+    /// Stored hashes need one more byte which identifies the variant.
+    pub fn byte_len(&self) -> usize {
+        match self {
+            &Hash::None => 0,
+            &Hash::Sha3(_) => 32
+        }
+    }
+
     /// Get the hash as byte array.
     pub fn get_bytes(&self) -> Box<[u8]>{
         match self {
@@ -100,11 +126,9 @@ impl Hash {
 
     /// Returns a sha3-256 hash of the byte array.
     pub fn hash_bytes(bytes: &[u8]) -> Hash {
-        let mut sha3 = Sha3::sha3_256();
-        sha3.input(bytes);
-        let mut res = [0u8; 32];
-        sha3.result(&mut res);
-        Hash::Sha3(res)
+        let mut hasher = Hasher::new();
+        hasher.update(bytes);
+        hasher.finish()
     }
 
     /// Generate a new hash by compining this hash with another one.
@@ -116,9 +140,59 @@ impl Hash {
     }
 }
 
+/// Generates a sha3-256 hash from data which is passed in chunks.
+///
+/// # Examples
+/// ```
+/// use tbd::hash::*;
+///
+/// let mut hasher = Hasher::new();
+/// hasher.update(b"Hello ");
+/// hasher.update(b"World");
+/// assert_eq!(Hash::hash_bytes(b"Hello World"), hasher.finish());
+/// ```
+pub struct Hasher {
+    sha3: Sha3
+}
+
+impl Hasher {
+    pub fn new() -> Hasher {
+        Hasher {
+            sha3: Sha3::sha3_256()
+        }
+    }
+
+    /// Add the bytes to the hashed data.
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.sha3.input(bytes);
+    }
+
+    /// Returns the hash of all passed bytes.
+    pub fn finish(mut self) -> Hash {
+        let mut res = [0u8; 32];
+        self.sha3.result(&mut res);
+        Hash::Sha3(res)
+    }
+}
+
+impl Default for Hasher {
+    fn default() -> Hasher {
+        Hasher::new()
+    }
+}
+
 /// Can generate a hash type which represents the current type.
 pub trait Hashable {
     fn as_hash(&self) -> Hash;
+
+    /// Compare two values by their hashes.
+    ///
+    /// Can be faster than == for big structures, especially if the hash is
+    /// cached like in HashCache.  Values with equal hashes are considered
+    /// equal, so this relies on the collision resistance of the hash.
+    fn hash_eq(&self, other: &Self) -> bool {
+        self.as_hash() == other.as_hash()
+    }
 }
 
 impl Hashable for Hash {
@@ -127,6 +201,59 @@ impl Hashable for Hash {
     }
 }
 
+/// Wraps a Hashable and remembers its hash.
+///
+/// The hash is calculated on the first as_hash call and reused until the
+/// value is borrowed mutably with get_mut.  This is useful for big values
+/// like the whole task state which are hashed often but change rarely.
+#[derive(Debug, Clone)]
+pub struct HashCache<T: Hashable> {
+    value: T,
+    hash: Cell<Option<Hash>>
+}
+
+impl<T: Hashable> HashCache<T> {
+    pub fn new(value: T) -> HashCache<T> {
+        HashCache {
+            value: value,
+            hash: Cell::new(None)
+        }
+    }
+
+    /// Borrow the value.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Borrow the value mutably and forget the cached hash.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.hash.set(None);
+        &mut self.value
+    }
+
+    /// Check if the hash is currently cached.
+    pub fn is_cached(&self) -> bool {
+        self.hash.get().is_some()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: Hashable> Hashable for HashCache<T> {
+    fn as_hash(&self) -> Hash {
+        match self.hash.get() {
+            Some(hash) => hash,
+            None => {
+                let hash = self.value.as_hash();
+                self.hash.set(Some(hash));
+                hash
+            }
+        }
+    }
+}
+
 /// Implement Hashable for any Debug
 ///
 /// This macro generates a valid Hashable implementation
@@ -167,3 +294,117 @@ macro_rules! hashable_for_debug {
 
 
 
+
+/// Implement Hashable for any Debug and verify the Debug output is stable
+///
+/// Works like hashable_for_debug but in debug builds, the Debug output is
+/// generated twice and it panics if they differ.  This catches Debug
+/// implementations which are not deterministic, for example because they
+/// contain a HashMap, which would produce different hashes for equal values.
+///
+/// # Examples
+/// ```
+/// #[macro_use] extern crate tbd;
+///
+/// use tbd::hash::*;
+///
+/// #[derive(Debug)]
+/// struct A {
+///    x: u8
+/// }
+/// hashable_for_debug_checked!(A);
+///
+/// fn main() {
+///     let a = A {x: 42u8};
+///     let hash: Hash = a.as_hash();
+///     assert_eq!("dbd0820fbce3804d3edc974e8e31cdee04172029528ea50b25db44356911fac1",
+///                   hash.as_string());
+/// }
+/// ```
+#[macro_export]
+macro_rules! hashable_for_debug_checked {
+    ($debug_type:path) => {
+        impl Hashable for $debug_type {
+            fn as_hash(&self) -> Hash {
+                let string_value = format!("{:?}", self);
+                if cfg!(debug_assertions) {
+                    let second_value = format!("{:?}", self);
+                    if string_value != second_value {
+                        panic!("Debug output of {} is not deterministic: {} != {}",
+                               stringify!($debug_type), string_value, second_value);
+                    }
+                }
+                Hash::hash_bytes(string_value.as_bytes())
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::fmt;
+
+    #[test]
+    fn hasher_test() {
+        let mut hasher = Hasher::new();
+        hasher.update(b"first chunk, ");
+        hasher.update(b"second chunk");
+        assert_eq!(Hash::hash_bytes(b"first chunk, second chunk"), hasher.finish());
+        assert_eq!(Hash::hash_bytes(b""), Hasher::new().finish());
+    }
+
+    struct Unstable {
+        calls: Cell<u32>
+    }
+
+    impl fmt::Debug for Unstable {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.calls.set(self.calls.get() + 1);
+            write!(f, "Unstable({})", self.calls.get())
+        }
+    }
+
+    hashable_for_debug_checked!(Unstable);
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "not deterministic")]
+    fn debug_checked_test() {
+        let unstable = Unstable { calls: Cell::new(0) };
+        unstable.as_hash();
+    }
+
+    #[test]
+    fn is_none_test() {
+        assert!(Hash::None.is_none());
+        assert!(!Hash::None.is_some());
+        assert!(Hash::none().is_none());
+        assert!(Hash::hash_bytes(b"x").is_some());
+        assert!(!Hash::hash_bytes(b"x").is_none());
+    }
+
+    #[test]
+    fn hash_map_key_test() {
+        use std::collections::HashMap;
+        let mut map = HashMap::new();
+        map.insert(Hash::None, "none");
+        map.insert(Hash::hash_bytes(b"one"), "one");
+        map.insert(Hash::hash_bytes(b"two"), "two");
+        map.insert(Hash::hash_bytes(b"one"), "one again");
+        assert_eq!(3, map.len());
+        assert_eq!(Some(&"none"), map.get(&Hash::none()));
+        assert_eq!(Some(&"one again"), map.get(&Hash::hash_bytes(b"one")));
+        assert_eq!(Some(&"two"), map.get(&Hash::hash_bytes(b"two")));
+        assert_eq!(None, map.get(&Hash::hash_bytes(b"three")));
+    }
+
+    #[test]
+    fn byte_len_test() {
+        assert_eq!(0, Hash::None.byte_len());
+        assert_eq!(32, Hash::hash_bytes(b"x").byte_len());
+        assert_eq!(Hash::hash_bytes(b"x").get_bytes().len(), Hash::hash_bytes(b"x").byte_len());
+    }
+}
@@ -13,17 +13,21 @@
 
 extern crate crypto;
 extern crate byteorder;
+extern crate time;
 
 
-use std::io::{Read, Write};
+use std::io::{Read, Write, BufRead, BufReader, BufWriter};
 use std::{io, error, fmt};
 use hash::*;
 use io::*;
-use std::fs::{File, create_dir_all};
-use std::collections::BTreeMap;
+use std::fs::{File, create_dir_all, read_dir, metadata};
+use std::collections::{BTreeMap, BTreeSet};
 use std::vec::Vec;
 use std::path::Path;
 use std::fs::rename;
+use std::convert::TryFrom;
+use std::time::UNIX_EPOCH;
+use self::time::Tm;
 
 
 
@@ -31,7 +35,10 @@ use std::fs::rename;
 pub enum HashIOError {
     Undefined(String),
     IOError(io::Error),
-    ParseError(Box<error::Error>)
+    ParseError(Box<error::Error>),
+    TypeError(String),
+    Corrupted(String),
+    VersionError(String)
 }
 
 
@@ -41,7 +48,10 @@ impl fmt::Display for HashIOError {
         match *self {
             HashIOError::Undefined(ref msg) => write!(f, "Undefined error: {}", msg),
             HashIOError::IOError(ref err) => err.fmt(f),
-            HashIOError::ParseError(ref err) => write!(f, "Parse error: {}", err)
+            HashIOError::ParseError(ref err) => write!(f, "Parse error: {}", err),
+            HashIOError::TypeError(ref msg) => write!(f, "Type error: {}", msg),
+            HashIOError::Corrupted(ref msg) => write!(f, "Corrupted entry: {}", msg),
+            HashIOError::VersionError(ref msg) => write!(f, "Version error: {}", msg)
         }
     }
 }
@@ -51,7 +61,29 @@ impl error::Error for HashIOError {
         match *self {
             HashIOError::Undefined(ref msg) => msg,
             HashIOError::IOError(ref err) => err.description(),
-            HashIOError::ParseError(ref err) => err.description()
+            HashIOError::ParseError(ref err) => err.description(),
+            HashIOError::TypeError(ref msg) => msg,
+            HashIOError::Corrupted(ref msg) => msg,
+            HashIOError::VersionError(ref msg) => msg
+        }
+    }
+}
+
+impl HashIOError {
+    /// Returns true if the error was caused by data which could not be
+    /// decoded as the requested type.
+    ///
+    /// A VersionError counts as well, because data of another type can look
+    /// like a model with an unknown version.
+    pub fn is_decode_error(&self) -> bool {
+        match *self {
+            HashIOError::TypeError(_) | HashIOError::ParseError(_) |
+                HashIOError::VersionError(_) => true,
+            HashIOError::IOError(ref err) => match err.kind() {
+                io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData => true,
+                _ => false
+            },
+            HashIOError::Undefined(_) | HashIOError::Corrupted(_) => false
         }
     }
 }
@@ -63,7 +95,89 @@ impl From<io::Error> for HashIOError {
 }
 
 pub struct HashIO {
-    pub base_path: String
+    /// Directory of the store without trailing slash, see with_base_path.
+    pub base_path: String,
+    /// Store new entries with a frame header, see with_framed.
+    pub framed: bool,
+    /// Sync new entries to disk before they are renamed, see with_fsync.
+    pub fsync: bool,
+    /// Appended to the name of the temporary file of a new entry.
+    pub temp_suffix: String,
+    /// Directory for temporary files, see with_temp_dir.
+    pub temp_dir: Option<String>
+}
+
+/// Version of the format the containers like Vec and BTreeMap are stored in.
+///
+/// Reading a container with a newer version fails with a VersionError, like
+/// reading a model with a newer version.
+///
+/// It is 0 and not 1 because all data which was stored so far, containers
+/// and models, is written with version 0.  For the same reason there is no
+/// version which is too old: models and containers accept version 0 and
+/// only newer versions are rejected.  Models have their own version, passed
+/// to tbd_model!, since each model changes independently.
+pub const HASHIO_FORMAT_VERSION: u32 = 0;
+
+/// Magic bytes at the start of an entry stored with a frame header.
+pub const FRAME_MAGIC: [u8; 4] = *b"TBDF";
+
+/// Size of the frame header: the magic bytes and the payload length.
+pub const FRAME_HEADER_LEN: usize = 8;
+
+/// Result of HashIO::put_estimate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PutEstimate {
+    /// Number of entries which are not stored yet.
+    pub new_objects: usize,
+    /// Sum of the sizes of the new entries in bytes.
+    pub new_bytes: usize
+}
+
+/// Result of HashIO::dedup_stats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DedupStats {
+    /// Number of different entries reachable from the roots.
+    pub unique_blobs: usize,
+    /// Number of references to entries including the roots themselves.
+    pub total_references: usize,
+    /// Sum of the sizes of the different entries in bytes.
+    pub bytes_unique: usize
+}
+
+/// Write sink which only counts the written bytes.
+struct ByteCounter {
+    count: usize
+}
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        self.count += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+}
+
+/// Size of an entry visited by HashIO::walk.
+///
+/// The size is only calculated when get is called, so walks which don't
+/// need it don't serialize every entry.
+pub struct EntrySize<'a> {
+    calc: &'a Fn() -> Result<usize, HashIOError>
+}
+
+impl<'a> EntrySize<'a> {
+    pub fn new(calc: &'a Fn() -> Result<usize, HashIOError>) -> EntrySize<'a> {
+        EntrySize { calc: calc }
+    }
+
+    /// Size in bytes the entry takes in the store, see HashIO::entry_size.
+    pub fn get(&self) -> Result<usize, HashIOError> {
+        (self.calc)()
+    }
 }
 
 pub trait HashIOImpl<T: Hashable> {
@@ -75,20 +189,104 @@ pub trait HashIOImpl<T: Hashable> {
     fn store_childs(&self, _: &T) -> Result<(), HashIOError> {
         Ok(())
     }
+
+    /// Walk through all childs of the hashable.
+    ///
+    /// Types with childs must call walk for each of them so HashIO::walk
+    /// can traverse the whole tree.
+    fn walk_childs<F>(&self, _: &T, _: &mut F) -> Result<(), HashIOError>
+            where F: FnMut(&Hash, &EntrySize) -> Result<bool, HashIOError> {
+        Ok(())
+    }
+}
+
+/// Describes the fields of a model, generated by tbd_model!.
+///
+/// Useful for generic tooling like dumping or comparing stored models.
+pub trait HashIOSchema {
+    /// Name and type of each field in the order they are stored.
+    fn schema() -> Vec<(&'static str, &'static str)>;
 }
 
 impl HashIO {
     pub fn new(path: String) -> HashIO {
         HashIO {
-            base_path: path
+            base_path: normalize_base_path(path),
+            framed: false,
+            fsync: false,
+            temp_suffix: "_".to_string(),
+            temp_dir: None
+        }
+    }
+
+    /// Use another directory for the store.
+    ///
+    /// Trailing slashes are removed so the paths of the entries don't
+    /// contain double slashes.  The path is not canonicalized since the
+    /// directory may not exist yet, relative paths stay relative.
+    pub fn with_base_path<P: AsRef<Path>>(mut self, path: P) -> HashIO {
+        self.base_path = normalize_base_path(path.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// Store new entries with a frame header.
+    ///
+    /// The header contains magic bytes and the length of the payload, so a
+    /// truncated entry is detected before it is parsed.  Entries are read
+    /// correctly with and without header, independent of this setting.
+    pub fn with_framed(mut self, framed: bool) -> HashIO {
+        self.framed = framed;
+        self
+    }
+
+    /// Sync new entries and their directory to disk.
+    ///
+    /// Without it, a power loss right after a put can leave an entry
+    /// which exists but has no data on some file systems.  Disabled by
+    /// default since it makes writing a lot slower.
+    pub fn with_fsync(mut self, fsync: bool) -> HashIO {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Set the suffix of temporary files.
+    ///
+    /// New entries are first written to a temporary file which is renamed
+    /// when it is complete.  By default, it is named like the entry with a
+    /// trailing underscore.  Without a temp directory, the temporary file
+    /// would be the entry itself with an empty suffix and writing would not
+    /// be atomic anymore.  In this case, a warning is logged and the suffix
+    /// is not changed, so set the temp directory first.
+    pub fn with_temp_suffix(mut self, temp_suffix: String) -> HashIO {
+        if temp_suffix.is_empty() && self.temp_dir.is_none() {
+            warn!("Empty temp suffix without temp dir for {}, keeping {:?}",
+                  self.base_path, self.temp_suffix);
+            return self;
+        }
+        self.temp_suffix = temp_suffix;
+        self
+    }
+
+    /// Write temporary files to temp_dir instead of next to the entries.
+    ///
+    /// The directory must be on the same file system as the store because
+    /// files can't be renamed across file systems.  A warning is logged if
+    /// both exist and are on different file systems.
+    pub fn with_temp_dir(mut self, temp_dir: String) -> HashIO {
+        if !same_file_system(&self.base_path, &temp_dir) {
+            warn!("Temp dir {} is not on the file system of {}", temp_dir, self.base_path);
         }
+        self.temp_dir = Some(temp_dir);
+        self
     }
 
     pub fn directory_for_hash(&self, hash: &Hash) -> String {
         let hash_str = hash.as_string();
         let mut result = String::new();
         result.push_str(&self.base_path);
-        result.push('/');
+        if !self.base_path.ends_with('/') {
+            result.push('/');
+        }
         result.push_str(&hash_str[0..2]);
         result.push('/');
         result
@@ -101,40 +299,399 @@ impl HashIO {
         result
     }
 
+    /// Check if an entry for the hash is stored without reading it.
+    pub fn exists(&self, hash: &Hash) -> bool {
+        hash.is_some() && Path::new(&self.filename_for_hash(hash)).exists()
+    }
+
+    /// Load the entry of the hash as T.
+    ///
+    /// # Errors
+    /// Returns a TypeError if the stored entry was not completely consumed,
+    /// which means the entry is not of type T.  Returns Undefined for
+    /// Hash::None.
     pub fn get<T>(&self, hash: &Hash) -> Result<T, HashIOError>
                 where HashIO: HashIOImpl<T>,
                       T: Hashable {
+        try!(check_addressable(hash));
         let filename = self.filename_for_hash(hash);
-        let mut read = try!(File::open(filename));
+        let file = try!(File::open(filename));
+        let file_len = try!(file.metadata()).len();
+        let mut read = BufReader::new(file);
+        let is_framed = {
+            let buf = try!(read.fill_buf());
+            buf.len() >= FRAME_MAGIC.len() && buf[..FRAME_MAGIC.len()] == FRAME_MAGIC
+        };
+        if is_framed {
+            read.consume(FRAME_MAGIC.len());
+            let payload_len = try!(read_u32(&mut read)) as u64;
+            if payload_len + FRAME_HEADER_LEN as u64 != file_len {
+                return Err(HashIOError::Corrupted(
+                    format!("Entry {} has {} bytes but its header expects {}",
+                            hash.as_string(), file_len,
+                            payload_len + FRAME_HEADER_LEN as u64)));
+            }
+        }
         let result : T = try!(self.receive_hashable(&mut read));
+        let mut rest = [0u8; 1];
+        if try!(read.read(&mut rest)) > 0 {
+            return Err(HashIOError::TypeError(
+                format!("Entry {} has trailing data", hash.as_string())));
+        }
+        Ok(result)
+    }
+
+    /// Check if the entry of the hash can be loaded as T.
+    ///
+    /// Unlike exists, this reads the entry, so it is false for missing,
+    /// truncated or differently typed entries.
+    pub fn contains_typed<T>(&self, hash: &Hash) -> bool
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable {
+        self.get::<T>(hash).is_ok()
+    }
+
+    /// Get all entries of the given hashes in one pass.
+    ///
+    /// The result has the same order as the given hashes.  Fails on the
+    /// first entry which could not be loaded.
+    pub fn get_many<T>(&self, hashes: &[Hash]) -> Result<Vec<T>, HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable {
+        let mut result = Vec::with_capacity(hashes.len());
+        for hash in hashes {
+            result.push(try!(self.get(hash)));
+        }
         Ok(result)
     }
 
-    pub fn put<T>(&self, hashable: &T) -> Result<(), HashIOError>
+    /// Store the hashable and all of its childs.
+    ///
+    /// Returns the hash under which the hashable is stored.
+    pub fn put<T>(&self, hashable: &T) -> Result<Hash, HashIOError>
                 where HashIO: HashIOImpl<T>,
                       T: Hashable {
-        let hash = hashable.as_hash();
+        self.put_hashed(hashable, hashable.as_hash())
+    }
 
+    /// Store the hashable whose hash was already calculated.
+    fn put_hashed<T>(&self, hashable: &T, hash: Hash) -> Result<Hash, HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable {
         // First, if the entry already exists, skip the insert because it's already saved.
-        let filename = self.filename_for_hash(&hash);
-        if !Path::new(&filename).exists() {
+        if !self.exists(&hash) {
             // First store all childs and their childs.
             // So we make sure that all dependencies are available when the current object has
             // finished writing.
             try!(self.store_childs(hashable));
+            if self.framed {
+                let mut payload = Vec::new();
+                try!(self.store_hashable(hashable, &mut payload));
+                try!(self.write_file(&hash, |write| {
+                    try!(write.write_all(&FRAME_MAGIC));
+                    try!(write_u32(payload.len() as u32, write));
+                    try!(write.write_all(&payload));
+                    Ok(())
+                }));
+            } else {
+                try!(self.write_file(&hash, |write| self.store_hashable(hashable, write)));
+            }
+        }
+        Ok(hash)
+    }
+
+    /// Store all items and return their hashes in the same order.
+    ///
+    /// Items which occur multiple times are only stored once.  Fails on
+    /// the first item which could not be stored, items before it stay
+    /// stored.
+    pub fn put_all<T>(&self, items: &[T]) -> Result<Vec<Hash>, HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable {
+        let mut stored = BTreeSet::new();
+        let mut hashes = Vec::with_capacity(items.len());
+        for item in items {
+            let hash = item.as_hash();
+            if stored.insert(hash) {
+                try!(self.put_hashed(item, hash));
+            }
+            hashes.push(hash);
+        }
+        Ok(hashes)
+    }
+
+    /// Walk through the hashable and all of its childs.
+    ///
+    /// visit is called with the hash and the size of every entry in the tree,
+    /// parents before their childs.  The childs of an entry are only visited
+    /// if visit returns true for it.  Childs which are referenced multiple
+    /// times are also visited multiple times.
+    pub fn walk<T, F>(&self, hashable: &T, visit: &mut F) -> Result<(), HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable,
+                      F: FnMut(&Hash, &EntrySize) -> Result<bool, HashIOError> {
+        let size = || self.entry_size(hashable);
+        if try!(visit(&hashable.as_hash(), &EntrySize::new(&size))) {
+            try!(self.walk_childs(hashable, visit));
+        }
+        Ok(())
+    }
+
+    /// Size in bytes the hashable takes in the store, without its childs.
+    pub fn entry_size<T>(&self, hashable: &T) -> Result<usize, HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable {
+        let mut counter = ByteCounter { count: 0 };
+        try!(self.store_hashable(hashable, &mut counter));
+        if self.framed {
+            counter.count += FRAME_HEADER_LEN;
+        }
+        Ok(counter.count)
+    }
+
+    /// Calculate how many entries and bytes a put of the hashable would write.
+    ///
+    /// Nothing is written.  Entries which are already stored are skipped
+    /// together with their childs, just like put does.
+    pub fn put_estimate<T>(&self, hashable: &T) -> Result<PutEstimate, HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable {
+        let mut res = PutEstimate { new_objects: 0, new_bytes: 0 };
+        let mut seen = BTreeSet::new();
+        try!(self.walk(hashable, &mut |hash: &Hash, size: &EntrySize| {
+            if self.exists(hash) || !seen.insert(*hash) {
+                Ok(false)
+            } else {
+                res.new_objects += 1;
+                res.new_bytes += try!(size.get());
+                Ok(true)
+            }
+        }));
+        Ok(res)
+    }
+
+    /// Copy the entry of the hash and everything it references into dest.
+    ///
+    /// Entries which already exist in dest are skipped together with their
+    /// childs.  Childs are copied before their parents, so dest never
+    /// contains an entry without its childs.  Returns the number of copied
+    /// entries.
+    pub fn copy_tree<T>(&self, dest: &HashIO, root: &Hash) -> Result<usize, HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable {
+        let hashable: T = try!(self.get(root));
+        let mut hashes = Vec::new();
+        try!(self.walk(&hashable, &mut |hash: &Hash, _: &EntrySize| {
+            if dest.exists(hash) {
+                Ok(false)
+            } else {
+                hashes.push(*hash);
+                Ok(true)
+            }
+        }));
+        let mut count = 0;
+        for hash in hashes.iter().rev() {
+            if !dest.exists(hash) {
+                try!(self.copy_to(dest, hash));
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// List the entries reachable from root which are not stored in other.
+    ///
+    /// The whole tree is traversed, so a child missing in other is also
+    /// found if its parent exists there.  Each hash is listed once, parents
+    /// before their childs.
+    pub fn missing_in<T>(&self, other: &HashIO, root: &Hash) -> Result<Vec<Hash>, HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable {
+        let hashable: T = try!(self.get(root));
+        let mut seen = BTreeSet::new();
+        let mut missing = Vec::new();
+        try!(self.walk(&hashable, &mut |hash: &Hash, _: &EntrySize| {
+            if !seen.insert(*hash) {
+                return Ok(false);
+            }
+            if !other.exists(hash) {
+                missing.push(*hash);
+            }
+            Ok(true)
+        }));
+        Ok(missing)
+    }
+
+    /// Count how often the entries reachable from the roots are referenced.
+    ///
+    /// Every reference is counted, also if it points to an entry which was
+    /// already seen, while unique_blobs and bytes_unique count each entry
+    /// only once.  The difference shows how much storage is saved because
+    /// equal entries are only stored once.
+    pub fn dedup_stats<T>(&self, roots: &[Hash]) -> Result<DedupStats, HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable {
+        let mut res = DedupStats {
+            unique_blobs: 0,
+            total_references: 0,
+            bytes_unique: 0
+        };
+        let mut seen = BTreeSet::new();
+        for root in roots {
+            let hashable: T = try!(self.get(root));
+            try!(self.walk(&hashable, &mut |hash: &Hash, size: &EntrySize| {
+                res.total_references += 1;
+                if seen.insert(*hash) {
+                    res.unique_blobs += 1;
+                    res.bytes_unique += try!(size.get());
+                }
+                Ok(true)
+            }));
+        }
+        Ok(res)
+    }
+
+    /// Get the size in bytes of the stored entry of the hash.
+    pub fn stored_size(&self, hash: &Hash) -> Result<usize, HashIOError> {
+        try!(check_addressable(hash));
+        let metadata = try!(metadata(self.filename_for_hash(hash)));
+        Ok(metadata.len() as usize)
+    }
+
+    /// Get the time the entry of the hash was first written to this store.
+    ///
+    /// Entries are never overwritten, so the modification time of the file
+    /// is used.  Returns None if the entry doesn't exist or the file system
+    /// doesn't provide the time.  The time is in UTC.
+    pub fn stored_at(&self, hash: &Hash) -> Option<Tm> {
+        if hash.is_none() {
+            return None;
+        }
+        let modified = match metadata(self.filename_for_hash(hash))
+                .and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(_) => return None
+        };
+        match modified.duration_since(UNIX_EPOCH) {
+            Ok(duration) => Some(time::at_utc(time::Timespec::new(
+                duration.as_secs() as i64, duration.subsec_nanos() as i32))),
+            Err(_) => None
+        }
+    }
+
+    /// Get the stored bytes of the hash without decoding them.
+    pub fn get_raw(&self, hash: &Hash) -> Result<Vec<u8>, HashIOError> {
+        try!(check_addressable(hash));
+        let mut file = try!(File::open(self.filename_for_hash(hash)));
+        let mut bytes = Vec::new();
+        try!(file.read_to_end(&mut bytes));
+        Ok(bytes)
+    }
+
+    /// Store the bytes under the given hash.
+    ///
+    /// The bytes are not verified, it's the responsibility of the caller that
+    /// they belong to the hash.  Like put, an existing entry is not
+    /// overwritten.
+    pub fn put_raw(&self, hash: &Hash, bytes: &[u8]) -> Result<(), HashIOError> {
+        if !self.exists(hash) {
+            try!(self.write_file(hash, |write| {
+                try!(write.write_all(bytes));
+                Ok(())
+            }));
+        }
+        Ok(())
+    }
+
+    /// Copy the stored bytes of the hash into another HashIO.
+    ///
+    /// Only the entry itself is copied, not its childs.
+    pub fn copy_to(&self, other: &HashIO, hash: &Hash) -> Result<(), HashIOError> {
+        if !other.exists(hash) {
+            let bytes = try!(self.get_raw(hash));
+            try!(other.put_raw(hash, &bytes));
+        }
+        Ok(())
+    }
+
+    /// List the hashes of all stored entries.
+    ///
+    /// Files in the store which are not named like an entry, for example
+    /// unfinished writes or a log head, are ignored.
+    pub fn list_hashes(&self) -> Result<Vec<Hash>, HashIOError> {
+        let mut res = Vec::new();
+        if !Path::new(&self.base_path).exists() {
+            return Ok(res);
+        }
+        for dir_entry in try!(read_dir(&self.base_path)) {
+            let dir_entry = try!(dir_entry);
+            let dir_name = dir_entry.file_name().to_string_lossy().into_owned();
+            if dir_name.len() != 2 || !is_lower_hex(&dir_name) ||
+                    !try!(dir_entry.file_type()).is_dir() {
+                continue;
+            }
+            for file_entry in try!(read_dir(dir_entry.path())) {
+                let file_entry = try!(file_entry);
+                let file_name = file_entry.file_name().to_string_lossy().into_owned();
+                if file_name.len() == 62 && is_lower_hex(&file_name) {
+                    res.push(Hash::from_string(format!("{}{}", dir_name, file_name)));
+                }
+            }
+        }
+        res.sort();
+        Ok(res)
+    }
+
+    /// Iterate over all stored entries which can be read as T.
+    ///
+    /// Entries which fail to decode as T are skipped, so a store with mixed
+    /// types can be scanned for one type.  Other errors like failing file
+    /// access are passed to the caller.
+    pub fn iter_typed<'a, T>(&'a self) -> Result<impl Iterator<Item=Result<T, HashIOError>> + 'a,
+                                              HashIOError>
+                where HashIO: HashIOImpl<T>,
+                      T: Hashable + 'a {
+        let hashes = try!(self.list_hashes());
+        Ok(hashes.into_iter()
+            .map(move |hash| self.get::<T>(&hash))
+            .filter(|res| match res {
+                &Err(ref err) => !err.is_decode_error(),
+                &Ok(_) => true
+            }))
+    }
 
-            // First write in a slightly modified file which will be renamed when writing was
-            // finished.  So we only have valid files or nothing on the expected position but
-            // nothing unfinished.
-            let safe_filename = format!("{}_", filename);
-            let dir = self.directory_for_hash(&hash);
-            try!(create_dir_all(dir));
-            {
-                let mut write = try!(File::create(Path::new(&safe_filename)));
-                try!(self.store_hashable(hashable, &mut write));
-                // 'write' will go out of scope now and so the file handle will be closed
+    /// Write the file of the hash with the given function.
+    ///
+    /// First write in a slightly modified file which will be renamed when writing was
+    /// finished.  So we only have valid files or nothing on the expected position but
+    /// nothing unfinished.
+    fn write_file<F>(&self, hash: &Hash, write_fn: F) -> Result<(), HashIOError>
+                where F: FnOnce(&mut BufWriter<File>) -> Result<(), HashIOError> {
+        try!(check_addressable(hash));
+        let filename = self.filename_for_hash(hash);
+        let safe_filename = match self.temp_dir {
+            Some(ref temp_dir) => {
+                try!(create_dir_all(temp_dir));
+                format!("{}/{}{}", temp_dir, hash.as_string(), self.temp_suffix)
+            },
+            None if self.temp_suffix.is_empty() => format!("{}_", filename),
+            None => format!("{}{}", filename, self.temp_suffix)
+        };
+        let dir = self.directory_for_hash(hash);
+        try!(create_dir_all(&dir));
+        {
+            let file = try!(File::create(Path::new(&safe_filename)));
+            let mut write = BufWriter::new(file);
+            try!(write_fn(&mut write));
+            try!(write.flush());
+            if self.fsync {
+                try!(write.get_ref().sync_all());
             }
-            try!(rename(safe_filename, filename));
+            // 'write' will go out of scope now and so the file handle will be closed
+        }
+        try!(rename(safe_filename, filename));
+        if self.fsync {
+            try!(try!(File::open(&dir)).sync_all());
         }
         Ok(())
     }
@@ -142,7 +699,7 @@ impl HashIO {
 
 
 
-impl Writable for String {
+impl Writable for str {
     fn write_to<W: Write>(&self, write: &mut W) -> Result<usize, io::Error> {
         let str_bytes = self.as_bytes();
         let len = usize_to_u32_bytes(str_bytes.len());
@@ -152,6 +709,13 @@ impl Writable for String {
         Ok(size)
     }
 }
+hashable_for_writable!(str);
+
+impl Writable for String {
+    fn write_to<W: Write>(&self, write: &mut W) -> Result<usize, io::Error> {
+        self.as_str().write_to(write)
+    }
+}
 hashable_for_writable!(String);
 
 impl HashIOImpl<String> for HashIO {
@@ -175,25 +739,115 @@ impl HashIOImpl<String> for HashIO {
 
 
 
+/// Fail for Hash::None because it has no entry in the store.
+fn check_addressable(hash: &Hash) -> Result<(), HashIOError> {
+    if hash.is_none() {
+        return Err(HashIOError::Undefined("cannot address Hash::None".to_string()));
+    }
+    Ok(())
+}
+
+/// Check if both paths are on the same file system.
+///
+/// Returns true if it can't be determined, for example if a path doesn't
+/// exist yet.
+#[cfg(unix)]
+fn same_file_system(a: &str, b: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (metadata(a), metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file_system(_: &str, _: &str) -> bool {
+    true
+}
+
+/// Remove trailing slashes from path but keep the root directory.
+fn normalize_base_path(path: String) -> String {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() && !path.is_empty() {
+        "/".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn is_lower_hex(s: &str) -> bool {
+    s.bytes().all(|c| (b'0'..=b'9').contains(&c) || (b'a'..=b'f').contains(&c))
+}
+
+/// Read the version of a container and fail if it is newer than the known one.
+fn expect_container_version<R>(read: &mut R) -> Result<(), HashIOError>
+            where R: Read {
+    let version = try!(read_u32(read));
+    if version > HASHIO_FORMAT_VERSION {
+        return Err(HashIOError::VersionError(
+            format!("Container version {} is newer than the supported version {}",
+                    version, HASHIO_FORMAT_VERSION)));
+    }
+    Ok(())
+}
+
+/// Generate a model which can be stored in HashIO.
+///
+/// The first list contains the attributes which are stored directly with
+/// their write and read functions.  The second list contains attributes which
+/// are stored as their own entry and only referenced by their hash.
+///
+/// Attributes which are added to an existing model would break reading the
+/// data which was already stored.  For this, a model version and a third list
+/// can be passed.  Each of its attributes is written after the hashes and
+/// contains the version since when it is stored and a default value which is
+/// used when older data is read.
+///
+/// A model is written with the lowest version which can hold its values, so
+/// attributes which still have their default value are not written.  This
+/// way, old data is written back byte by byte after it was loaded and keeps
+/// its hash, which is referenced by logs and parent models.
+///
+/// The model also implements HashIOSchema which lists all attributes.
 macro_rules! tbd_model {
     ($model_name:ident,
             [ $( [$attr_name:ident : $attr_type:ty, $exp_fn:ident, $imp_fn:ident ] ),* ] ,
             [ $( [$hash_name:ident : $hash_type:ty] ),* ]) => {
+        tbd_model!($model_name,
+            [ $( [$attr_name : $attr_type, $exp_fn, $imp_fn] ),* ],
+            [ $( [$hash_name : $hash_type] ),* ],
+            0, []);
+    };
+    ($model_name:ident,
+            [ $( [$attr_name:ident : $attr_type:ty, $exp_fn:ident, $imp_fn:ident ] ),* ] ,
+            [ $( [$hash_name:ident : $hash_type:ty] ),* ] ,
+            $version:expr ,
+            [ $( [$ext_name:ident : $ext_type:ty, $ext_exp_fn:ident, $ext_imp_fn:ident,
+                  $since:expr, $default:expr ] ),* ]) => {
 
         #[derive(Debug, Clone, PartialEq)]
         pub struct $model_name {
             $(pub $attr_name: $attr_type,)*
-            $(pub $hash_name: $hash_type),*
+            $(pub $hash_name: $hash_type,)*
+            $(pub $ext_name: $ext_type),*
         }
 
         impl Writable for $model_name {
             fn write_to<W: Write>(&self, write: &mut W) -> Result<usize, io::Error> {
-                let mut size = 0;
-                try!(write_u32(0, write));
-                size += $( try!($exp_fn(self.$attr_name, write)); )*
+                #[allow(unused_mut)]
+                let mut version: u32 = 0;
+                $(
+                    if self.$ext_name != $default && $since > version {
+                        version = $since;
+                    }
+                )*
+                let mut size = try!(write_u32(version, write));
+                $( size += try!($exp_fn(self.$attr_name, write)); )*
+                $( size += try!(write_hash(&self.$hash_name.as_hash(), write)); )*
                 $(
-                    try!(write_hash(&self.$hash_name.as_hash(), write));
-                    size += 32;
+                    if version >= $since {
+                        size += try!($ext_exp_fn(self.$ext_name, write));
+                    }
                 )*
                 Ok(size)
             }
@@ -201,10 +855,25 @@ macro_rules! tbd_model {
 
         hashable_for_writable!($model_name);
 
+        impl HashIOSchema for $model_name {
+            fn schema() -> Vec<(&'static str, &'static str)> {
+                vec![
+                    $( (stringify!($attr_name), stringify!($attr_type)), )*
+                    $( (stringify!($hash_name), stringify!($hash_type)), )*
+                    $( (stringify!($ext_name), stringify!($ext_type)), )*
+                ]
+            }
+        }
+
         impl HashIOImpl<$model_name> for HashIO {
             fn receive_hashable<R>(&self, read: &mut R) -> Result<$model_name, HashIOError>
                     where R: Read {
-                try!(read_u32(read));
+                let version = try!(read_u32(read));
+                if version > $version {
+                    return Err(HashIOError::VersionError(
+                        format!("{} version {} is newer than the supported version {}",
+                                stringify!($model_name), version, $version)));
+                }
                 $( let $attr_name = try!($imp_fn(read)); )* ;
                 $(
                     let $hash_name;
@@ -213,9 +882,17 @@ macro_rules! tbd_model {
                         $hash_name = try!(self.get(&hash_val));
                     }
                 )*
+                $(
+                    let $ext_name = if version >= $since {
+                        try!($ext_imp_fn(read))
+                    } else {
+                        $default
+                    };
+                )*
                 Ok($model_name{
                     $($attr_name: $attr_name,)*
-                    $($hash_name: $hash_name),*
+                    $($hash_name: $hash_name,)*
+                    $($ext_name: $ext_name),*
                     })
             }
 
@@ -224,6 +901,13 @@ macro_rules! tbd_model {
                 Ok(())
             }
 
+            #[allow(unused_variables)]
+            fn walk_childs<F>(&self, hashable: &$model_name, visit: &mut F) -> Result<(), HashIOError>
+                    where F: FnMut(&Hash, &EntrySize) -> Result<bool, HashIOError> {
+                $( try!(self.walk(&hashable.$hash_name, visit)); )*
+                Ok(())
+            }
+
             fn store_hashable<W>(&self, hashable: &$model_name, write: &mut W) -> Result<(), HashIOError>
                     where W: Write {
                 try!(hashable.write_to(write));
@@ -241,6 +925,9 @@ mod test {
     use super::super::hashio::*;
     use super::super::io::*;
     use std::io::{Read, Write};
+    use std::collections::BTreeMap;
+    use std::fs::remove_dir_all;
+    use std::path::Path;
 
     #[derive(Debug)]
     struct A {
@@ -260,7 +947,8 @@ mod test {
         }
 
         fn store_childs(&self, hashable: &A) -> Result<(), HashIOError> {
-            self.put(&hashable.b)
+            try!(self.put(&hashable.b));
+            Ok(())
         }
 
         fn store_hashable<W>(&self, hashable: &A, write: &mut W) -> Result<(), HashIOError>
@@ -288,6 +976,160 @@ mod test {
         assert_eq!(10, a2.a);
         assert_eq!("Test".to_string(), a2.b);
     }
+
+    #[test]
+    fn base_path_test() {
+        let hash = "base path".to_string().as_hash();
+        let hash_str = hash.as_string();
+        let expected = format!("savetest/basepath/{}/{}", &hash_str[0..2], &hash_str[2..]);
+
+        let hash_io = HashIO::new("savetest/basepath//".to_string());
+        assert_eq!("savetest/basepath", hash_io.base_path);
+        assert_eq!(expected, hash_io.filename_for_hash(&hash));
+        assert!(!hash_io.filename_for_hash(&hash).contains("//"));
+
+        let hash_io = HashIO::new("unused".to_string())
+            .with_base_path(Path::new("savetest/basepath/").to_path_buf());
+        assert_eq!(expected, hash_io.filename_for_hash(&hash));
+        let hash_io = HashIO::new("unused".to_string()).with_base_path("savetest/basepath");
+        assert_eq!(expected, hash_io.filename_for_hash(&hash));
+        hash_io.put(&"base path".to_string()).unwrap();
+        assert!(Path::new(&expected).exists());
+
+        let root = HashIO::new("/".to_string());
+        assert_eq!("/", root.base_path);
+        assert_eq!(format!("/{}/", &hash_str[0..2]), root.directory_for_hash(&hash));
+    }
+
+    #[test]
+    fn raw_test() {
+        let hash_io = HashIO::new("savetest".to_string());
+        let other = HashIO::new("savetest/rawtest".to_string());
+        let value = "raw value".to_string();
+        let hash = value.as_hash();
+        hash_io.put(&value).unwrap();
+
+        let bytes = hash_io.get_raw(&hash).unwrap();
+        let mut expected: Vec<u8> = Vec::new();
+        value.write_to(&mut expected).unwrap();
+        assert_eq!(expected, bytes);
+
+        other.put_raw(&hash, &bytes).unwrap();
+        assert_eq!(bytes, other.get_raw(&hash).unwrap());
+        let value_2: String = other.get(&hash).unwrap();
+        assert_eq!(value, value_2);
+    }
+
+    #[test]
+    fn copy_to_test() {
+        let hash_io = HashIO::new("savetest".to_string());
+        let other = HashIO::new("savetest/copytotest".to_string());
+        let value = "copied value".to_string();
+        let hash = value.as_hash();
+        hash_io.put(&value).unwrap();
+
+        hash_io.copy_to(&other, &hash).unwrap();
+        let value_2: String = other.get(&hash).unwrap();
+        assert_eq!(value, value_2);
+    }
+
+    #[test]
+    fn get_many_test() {
+        let hash_io = HashIO::new("savetest".to_string());
+        let values = vec!["one".to_string(), "two".to_string(), "three".to_string()];
+        let mut hashes = Vec::new();
+        for value in values.iter() {
+            hash_io.put(value).unwrap();
+            hashes.push(value.as_hash());
+        }
+
+        let loaded: Vec<String> = hash_io.get_many(&hashes).unwrap();
+        assert_eq!(values, loaded);
+    }
+
+    #[test]
+    fn empty_string_test() {
+        let hash_io = HashIO::new("savetest".to_string());
+        let empty = String::new();
+        hash_io.put(&empty).unwrap();
+        let loaded: String = hash_io.get(&empty.as_hash()).unwrap();
+        assert_eq!(empty, loaded);
+
+        // The hash of stored data must never change.
+        assert_eq!("8b0a2385d83c8bf7be27e59996f7d881d3bf1fc6606f81ce600b753ad94192a2", empty.as_hash().as_string());
+    }
+
+    #[test]
+    fn str_hash_test() {
+        assert_eq!("literal".to_string().as_hash(), "literal".as_hash());
+        assert_eq!(String::new().as_hash(), "".as_hash());
+    }
+
+    #[test]
+    fn long_string_test() {
+        let hash_io = HashIO::new("savetest".to_string());
+        let long: String = "0123456789abcdef".repeat(64 * 1024);
+        assert_eq!(1024 * 1024, long.len());
+        hash_io.put(&long).unwrap();
+        let loaded: String = hash_io.get(&long.as_hash()).unwrap();
+        assert_eq!(long, loaded);
+    }
+
+    #[test]
+    fn store_hashable_without_childs_test() {
+        let hash_io = HashIO::new("savetest/storechildstest".to_string());
+        remove_dir_all("savetest/storechildstest").ok();
+        let mut map = BTreeMap::new();
+        map.insert("key".to_string(), vec!["value".to_string()]);
+
+        // Only store_childs writes the childs, so a container can be
+        // serialized without writing anything.
+        let mut bytes = Vec::new();
+        hash_io.store_hashable(&map, &mut bytes).unwrap();
+        assert!(!hash_io.exists(&"key".as_hash()));
+        assert!(!hash_io.exists(&map["key"].as_hash()));
+
+        hash_io.store_childs(&map).unwrap();
+        assert!(hash_io.exists(&"key".as_hash()));
+        assert!(hash_io.exists(&map["key"].as_hash()));
+        assert!(hash_io.exists(&"value".as_hash()));
+        assert!(!hash_io.exists(&map.as_hash()));
+    }
+
+    #[test]
+    fn put_hash_test() {
+        let hash_io = HashIO::new("savetest".to_string());
+        let value = "put returns the hash".to_string();
+        let hash = hash_io.put(&value).unwrap();
+        assert_eq!(value.as_hash(), hash);
+        assert!(Path::new(&hash_io.filename_for_hash(&hash)).exists());
+        // Also for entries which are already stored
+        assert_eq!(hash, hash_io.put(&value).unwrap());
+    }
+
+    #[test]
+    fn type_error_test() {
+        let hash_io = HashIO::new("savetest".to_string());
+        let values = vec!["first".to_string(), "second".to_string()];
+        let string = "single".to_string();
+        hash_io.put(&values).unwrap();
+        hash_io.put(&string).unwrap();
+
+        let loaded: Vec<String> = hash_io.get(&values.as_hash()).unwrap();
+        assert_eq!(values, loaded);
+        let loaded: String = hash_io.get(&string.as_hash()).unwrap();
+        assert_eq!(string, loaded);
+
+        match hash_io.get::<String>(&values.as_hash()) {
+            Err(HashIOError::TypeError(_)) => (),
+            res => panic!("Expected TypeError, got {:?}", res)
+        }
+        // The length of the string is read as the container version.
+        match hash_io.get::<Vec<String>>(&string.as_hash()) {
+            Err(HashIOError::VersionError(_)) => (),
+            res => panic!("Expected VersionError, got {:?}", res)
+        }
+    }
 }
 
 
@@ -299,6 +1141,7 @@ mod test2 {
     use super::super::io::*;
     use std::io::{Read, Write};
     use std::io;
+    use std::fs::{File, remove_dir_all, remove_file};
 
     tbd_model!(A, [
         [a: u8, write_u8, read_u8]
@@ -337,50 +1180,405 @@ mod test2 {
         assert_eq!(b.foo, b_read.foo);
         assert_eq!(b.foobar, b_read.foobar);
     }
-}
 
+    #[test]
+    fn copy_tree_test() {
+        let hash_io = HashIO::new("savetest".to_string());
+        remove_dir_all("savetest/copytreetest").ok();
+        let dest = HashIO::new("savetest/copytreetest".to_string());
+        let b = B {
+            foo: "Foo".to_string(),
+            bar: A {
+                a: 20,
+                b: "Foo".to_string()
+            },
+            foobar: A {
+                a: 30,
+                b: "baz".to_string()
+            }
+        };
+        hash_io.put(&b).unwrap();
 
-impl<T, U> Writable for BTreeMap<T, U>
-    where T: Writable, U: Writable, T: Hashable, U: Hashable {
-    fn write_to<W: Write>(&self, write: &mut W) -> Result<usize, io::Error> {
-        try!(write_u32(0, write));
-        try!(write_u32(self.len() as u32, write));
-        let mut size: usize = 0;
-        for (key, value) in self {
-            size += try!(write_hash(&key.as_hash(), write));
-            size += try!(write_hash(&value.as_hash(), write));
-        }
-        Ok(size)
+        // b, "Foo", "baz" and both A entries
+        assert_eq!(5, hash_io.copy_tree::<B>(&dest, &b.as_hash()).unwrap());
+        let b_read: B = dest.get(&b.as_hash()).unwrap();
+        assert_eq!(b, b_read);
+        assert_eq!(0, hash_io.copy_tree::<B>(&dest, &b.as_hash()).unwrap());
     }
-}
 
-impl<T, U> Hashable for BTreeMap<T, U>
-    where BTreeMap<T, U>: Writable {
-    fn as_hash(&self) -> Hash {
-        self.writable_to_hash()
+    #[test]
+    fn schema_test() {
+        assert_eq!(vec![("foo", "String"), ("bar", "A"), ("foobar", "A")], B::schema());
     }
-}
 
-impl<T, U> HashIOImpl<BTreeMap<T, U>> for HashIO
-    where HashIO: HashIOImpl<T>,
+    #[test]
+    fn put_all_test() {
+        remove_dir_all("savetest/putalltest").ok();
+        let hash_io = HashIO::new("savetest/putalltest".to_string());
+        let mut items: Vec<String> = (0..50).map(|i| format!("item {}", i)).collect();
+        items.push("item 7".to_string());
+        let hashes = hash_io.put_all(&items).unwrap();
+        assert_eq!(items.len(), hashes.len());
+        assert_eq!(hashes[7], hashes[50]);
+        for (item, hash) in items.iter().zip(hashes.iter()) {
+            assert_eq!(item.as_hash(), *hash);
+            let item_read: String = hash_io.get(hash).unwrap();
+            assert_eq!(*item, item_read);
+        }
+        assert_eq!(50, hash_io.list_hashes().unwrap().len());
+    }
+
+    #[test]
+    fn none_hash_test() {
+        let hash_io = HashIO::new("savetest".to_string());
+        match hash_io.get::<String>(&Hash::None) {
+            Err(HashIOError::Undefined(ref msg)) => assert_eq!("cannot address Hash::None", msg),
+            res => panic!("Expected Undefined error, got {:?}", res)
+        }
+        match hash_io.get_raw(&Hash::None) {
+            Err(HashIOError::Undefined(_)) => (),
+            res => panic!("Expected Undefined error, got {:?}", res)
+        }
+        match hash_io.put_raw(&Hash::None, b"bytes") {
+            Err(HashIOError::Undefined(_)) => (),
+            res => panic!("Expected Undefined error, got {:?}", res)
+        }
+        assert!(hash_io.stored_size(&Hash::None).is_err());
+        assert!(!hash_io.exists(&Hash::None));
+        assert_eq!(None, hash_io.stored_at(&Hash::None));
+    }
+
+    #[test]
+    fn stored_at_test() {
+        remove_dir_all("savetest/storedattest").ok();
+        let hash_io = HashIO::new("savetest/storedattest".to_string());
+        let before = super::time::now_utc() - super::time::Duration::seconds(5);
+        let hash = hash_io.put(&"stored".to_string()).unwrap();
+        let after = super::time::now_utc() + super::time::Duration::seconds(5);
+        let stored_at = hash_io.stored_at(&hash).unwrap();
+        assert!(before <= stored_at && stored_at <= after);
+
+        // Storing it again keeps the time of the first write.
+        hash_io.put(&"stored".to_string()).unwrap();
+        assert_eq!(Some(stored_at), hash_io.stored_at(&hash));
+        assert_eq!(None, hash_io.stored_at(&Hash::hash_bytes(b"missing")));
+    }
+
+    #[test]
+    fn missing_in_test() {
+        remove_dir_all("savetest/missingintest").ok();
+        let hash_io = HashIO::new("savetest/missingintest/self".to_string());
+        let other = HashIO::new("savetest/missingintest/other".to_string());
+        let b = B {
+            foo: "Foo".to_string(),
+            bar: A { a: 20, b: "bar".to_string() },
+            foobar: A { a: 30, b: "foobar".to_string() }
+        };
+        hash_io.put(&b).unwrap();
+        other.put(&b).unwrap();
+        assert!(hash_io.missing_in::<B>(&other, &b.as_hash()).unwrap().is_empty());
+
+        remove_file(other.filename_for_hash(&b.bar.as_hash())).unwrap();
+        assert_eq!(vec![b.bar.as_hash()],
+                   hash_io.missing_in::<B>(&other, &b.as_hash()).unwrap());
+
+        let empty = HashIO::new("savetest/missingintest/empty".to_string());
+        assert_eq!(6, hash_io.missing_in::<B>(&empty, &b.as_hash()).unwrap().len());
+    }
+
+    #[test]
+    fn put_estimate_test() {
+        remove_dir_all("savetest/estimatetest").ok();
+        let hash_io = HashIO::new("savetest/estimatetest".to_string());
+        let b = B {
+            foo: "Foo".to_string(),
+            bar: A { a: 20, b: "Foo".to_string() },
+            foobar: A { a: 30, b: "baz".to_string() }
+        };
+
+        let estimate = hash_io.put_estimate(&b).unwrap();
+        hash_io.put(&b).unwrap();
+        let hashes = hash_io.list_hashes().unwrap();
+        assert_eq!(hashes.len(), estimate.new_objects);
+        let bytes: usize = hashes.iter().map(|hash| hash_io.stored_size(hash).unwrap()).sum();
+        assert_eq!(bytes, estimate.new_bytes);
+
+        let estimate = hash_io.put_estimate(&b).unwrap();
+        assert_eq!(PutEstimate { new_objects: 0, new_bytes: 0 }, estimate);
+    }
+
+    #[test]
+    fn framed_test() {
+        remove_dir_all("savetest/framedtest").ok();
+        let hash_io = HashIO::new("savetest/framedtest".to_string()).with_framed(true);
+        let b = B {
+            foo: "framed".to_string(),
+            bar: A { a: 1, b: "one".to_string() },
+            foobar: A { a: 2, b: "two".to_string() }
+        };
+        hash_io.put(&b).unwrap();
+        let b_read: B = hash_io.get(&b.as_hash()).unwrap();
+        assert_eq!(b, b_read);
+
+        let estimate = hash_io.put_estimate(&b);
+        assert_eq!(0, estimate.unwrap().new_objects);
+
+        // Entries without frame can still be read.
+        let plain = HashIO::new("savetest/framedtest".to_string());
+        plain.put(&"plain".to_string()).unwrap();
+        let plain_read: String = hash_io.get(&"plain".as_hash()).unwrap();
+        assert_eq!("plain", plain_read);
+
+        // Truncate the payload of a framed entry.
+        let filename = hash_io.filename_for_hash(&b.as_hash());
+        let bytes = hash_io.get_raw(&b.as_hash()).unwrap();
+        assert_eq!(FRAME_MAGIC[..], bytes[..4]);
+        let mut file = File::create(filename).unwrap();
+        file.write_all(&bytes[..bytes.len() - 5]).unwrap();
+        match hash_io.get::<B>(&b.as_hash()) {
+            Err(HashIOError::Corrupted(_)) => (),
+            res => panic!("Expected Corrupted error, got {:?}", res)
+        }
+    }
+
+    #[test]
+    fn fsync_test() {
+        remove_dir_all("savetest/fsynctest").ok();
+        let hash_io = HashIO::new("savetest/fsynctest".to_string()).with_fsync(true);
+        assert!(hash_io.fsync);
+        let value = "synced".to_string();
+        let hash = hash_io.put(&value).unwrap();
+        assert!(Path::new(&hash_io.filename_for_hash(&hash)).exists());
+        let value_read: String = hash_io.get(&hash).unwrap();
+        assert_eq!(value, value_read);
+    }
+
+    #[test]
+    fn temp_file_test() {
+        remove_dir_all("savetest/tempfiletest").ok();
+        let hash_io = HashIO::new("savetest/tempfiletest".to_string())
+            .with_temp_suffix(".tmp".to_string());
+        assert_eq!(".tmp", hash_io.temp_suffix);
+        // An empty suffix without temp dir would write to the entry itself.
+        assert_eq!(".tmp", hash_io.with_temp_suffix(String::new()).temp_suffix);
+        let hash_io = HashIO::new("savetest/tempfiletest".to_string())
+            .with_temp_suffix(".tmp".to_string());
+        let hash = hash_io.put(&"suffix".to_string()).unwrap();
+        let filename = hash_io.filename_for_hash(&hash);
+        assert!(Path::new(&filename).exists());
+        assert!(!Path::new(&format!("{}.tmp", filename)).exists());
+        let value: String = hash_io.get(&hash).unwrap();
+        assert_eq!("suffix", value);
+
+        let hash_io = HashIO::new("savetest/tempfiletest".to_string())
+            .with_temp_dir("savetest/tempfiletest/tmp".to_string())
+            .with_temp_suffix(String::new());
+        let hash = hash_io.put(&"temp dir".to_string()).unwrap();
+        assert!(Path::new(&hash_io.filename_for_hash(&hash)).exists());
+        assert!(!Path::new(&format!("savetest/tempfiletest/tmp/{}",
+                                    hash.as_string())).exists());
+        assert_eq!(2, hash_io.list_hashes().unwrap().len());
+    }
+
+    #[test]
+    fn dedup_stats_test() {
+        remove_dir_all("savetest/dedupstatstest").ok();
+        let hash_io = HashIO::new("savetest/dedupstatstest".to_string());
+        let b1 = B {
+            foo: "first".to_string(),
+            bar: A { a: 1, b: "shared".to_string() },
+            foobar: A { a: 2, b: "text".to_string() }
+        };
+        let b2 = B {
+            foo: "second".to_string(),
+            bar: A { a: 1, b: "shared".to_string() },
+            foobar: A { a: 3, b: "text".to_string() }
+        };
+        hash_io.put(&b1).unwrap();
+        hash_io.put(&b2).unwrap();
+
+        let stats = hash_io.dedup_stats::<B>(&[b1.as_hash(), b2.as_hash()]).unwrap();
+        // Each B references its foo string and two A entries with one string each.
+        assert_eq!(12, stats.total_references);
+        let hashes = hash_io.list_hashes().unwrap();
+        assert_eq!(9, hashes.len());
+        assert_eq!(hashes.len(), stats.unique_blobs);
+        let bytes: usize = hashes.iter().map(|hash| hash_io.stored_size(hash).unwrap()).sum();
+        assert_eq!(bytes, stats.bytes_unique);
+    }
+
+    #[test]
+    fn iter_typed_test() {
+        remove_dir_all("savetest/itertypedtest").ok();
+        let hash_io = HashIO::new("savetest/itertypedtest".to_string());
+        hash_io.put(&"first".to_string()).unwrap();
+        hash_io.put(&"second".to_string()).unwrap();
+        hash_io.put(&A { a: 1, b: "first".to_string() }).unwrap();
+
+        assert_eq!(3, hash_io.list_hashes().unwrap().len());
+        let mut strings: Vec<String> = hash_io.iter_typed::<String>().unwrap()
+            .map(|res| res.unwrap())
+            .collect();
+        strings.sort();
+        assert_eq!(vec!["first".to_string(), "second".to_string()], strings);
+    }
+
+    #[test]
+    fn buffered_write_test() {
+        let hash_io = HashIO::new("savetest/bufferedtest".to_string());
+        let b = B {
+            foo: "Foo".to_string(),
+            bar: A {
+                a: 40,
+                b: "bar".to_string()
+            },
+            foobar: A {
+                a: 50,
+                b: "foobar".to_string()
+            }
+        };
+        hash_io.put(&b).unwrap();
+
+        // The stored file must contain the complete serialization.
+        let mut expected: Vec<u8> = Vec::new();
+        b.write_to(&mut expected).unwrap();
+        let mut stored: Vec<u8> = Vec::new();
+        let mut file = File::open(hash_io.filename_for_hash(&b.as_hash())).unwrap();
+        file.read_to_end(&mut stored).unwrap();
+        assert_eq!(expected, stored);
+
+        let b_read: B = hash_io.get(&b.as_hash()).unwrap();
+        assert_eq!(b, b_read);
+    }
+
+    #[test]
+    fn buffered_read_test() {
+        let hash_io = HashIO::new("savetest/bufferedtest".to_string());
+        let bs = vec![
+            B {
+                foo: "first".to_string(),
+                bar: A { a: 1, b: "one".to_string() },
+                foobar: A { a: 2, b: "two".to_string() }
+            },
+            B {
+                foo: "second".to_string(),
+                bar: A { a: 3, b: "three".to_string() },
+                foobar: A { a: 1, b: "one".to_string() }
+            }
+        ];
+        hash_io.put(&bs).unwrap();
+
+        let bs_read: Vec<B> = hash_io.get(&bs.as_hash()).unwrap();
+        assert_eq!(bs, bs_read);
+    }
+
+    /// A child without an entry, which is written as Hash::None.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Nothing;
+
+    impl Hashable for Nothing {
+        fn as_hash(&self) -> Hash {
+            Hash::None
+        }
+    }
+
+    impl HashIOImpl<Nothing> for HashIO {
+        fn receive_hashable<R>(&self, _: &mut R) -> Result<Nothing, HashIOError>
+                    where R: Read {
+            Ok(Nothing)
+        }
+
+        fn store_hashable<W>(&self, _: &Nothing, _: &mut W) -> Result<(), HashIOError>
+                    where W: Write {
+            Ok(())
+        }
+    }
+
+    tbd_model!(C, [
+        [c: u8, write_u8, read_u8]
+     ], [
+        [nothing: Nothing]
+     ]);
+
+    #[test]
+    fn write_size_test() {
+        let a = A { a: 1, b: "one".to_string() };
+        let mut bytes = Vec::new();
+        let size = a.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+        assert_eq!(4 + 1 + 33, size);
+
+        // Hash::None is written as its identifier only.
+        let c = C { c: 1, nothing: Nothing };
+        let mut bytes = Vec::new();
+        let size = c.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+        assert_eq!(4 + 1 + 1, size);
+
+        let bs = vec![B { foo: "foo".to_string(), bar: a.clone(), foobar: a }];
+        let mut bytes = Vec::new();
+        let size = bs[0].write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+        let mut bytes = Vec::new();
+        let size = bs.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+    }
+}
+
+
+impl<T, U> Writable for BTreeMap<T, U>
+    where T: Writable, U: Writable, T: Hashable, U: Hashable {
+    fn write_to<W: Write>(&self, write: &mut W) -> Result<usize, io::Error> {
+        let mut size = try!(write_u32(HASHIO_FORMAT_VERSION, write));
+        size += try!(write_u32(self.len() as u32, write));
+        for (key, value) in self {
+            size += try!(write_hash(&key.as_hash(), write));
+            size += try!(write_hash(&value.as_hash(), write));
+        }
+        Ok(size)
+    }
+}
+
+impl<T, U> Hashable for BTreeMap<T, U>
+    where BTreeMap<T, U>: Writable {
+    fn as_hash(&self) -> Hash {
+        self.writable_to_hash()
+    }
+}
+
+impl<T, U> HashIOImpl<BTreeMap<T, U>> for HashIO
+    where HashIO: HashIOImpl<T>,
           HashIO: HashIOImpl<U>,
           T: Writable, U: Writable,
           T: Hashable, U: Hashable,
           T: Ord {
-    fn store_hashable<W>(&self, hashable: &BTreeMap<T, U>, write: &mut W) -> Result<(), HashIOError>
-        where W: Write {
+    fn store_childs(&self, hashable: &BTreeMap<T, U>) -> Result<(), HashIOError> {
         for (key, value) in hashable {
             try!(self.put(key));
             try!(self.put(value));
         }
+        Ok(())
+    }
+
+    fn store_hashable<W>(&self, hashable: &BTreeMap<T, U>, write: &mut W) -> Result<(), HashIOError>
+        where W: Write {
         try!(hashable.write_to(write));
         Ok(())
     }
 
+    fn walk_childs<F>(&self, hashable: &BTreeMap<T, U>, visit: &mut F) -> Result<(), HashIOError>
+        where F: FnMut(&Hash, &EntrySize) -> Result<bool, HashIOError> {
+        for (key, value) in hashable {
+            try!(self.walk(key, visit));
+            try!(self.walk(value, visit));
+        }
+        Ok(())
+    }
+
     fn receive_hashable<R>(&self, read: &mut R) -> Result<BTreeMap<T, U>, HashIOError>
         where R: Read {
         let mut res = BTreeMap::<T, U>::new();
-        try!(read_u32(read));
+        try!(expect_container_version(read));
         let entries = try!(read_u32(read));
         for _ in 0..entries {
             let key_hash = try!(read_hash(read));
@@ -393,6 +1591,126 @@ impl<T, U> HashIOImpl<BTreeMap<T, U>> for HashIO
     }
 }
 
+#[cfg(test)]
+mod versiontest {
+    use super::super::hash::*;
+    use super::super::hashio::*;
+    use super::super::io::*;
+    use std::io::{Read, Write};
+    use std::io;
+
+    tbd_model!(Old, [
+        [a: u8, write_u8, read_u8]
+     ], [
+        [b: String]
+     ]);
+
+    tbd_model!(New, [
+        [a: u8, write_u8, read_u8]
+     ], [
+        [b: String]
+     ], 1, [
+        [c: u8, write_u8, read_u8, 1, 7]
+     ]);
+
+    #[test]
+    fn test() {
+        let hash_io = HashIO::new("savetest/versiontest".to_string());
+        let old = Old { a: 1, b: "old".to_string() };
+        hash_io.put(&old).unwrap();
+        let upgraded: New = hash_io.get(&old.as_hash()).unwrap();
+        assert_eq!(New { a: 1, b: "old".to_string(), c: 7 }, upgraded);
+
+        let new = New { a: 2, b: "new".to_string(), c: 3 };
+        hash_io.put(&new).unwrap();
+        let new_2: New = hash_io.get(&new.as_hash()).unwrap();
+        assert_eq!(new, new_2);
+    }
+
+    #[test]
+    fn keep_hash_test() {
+        let hash_io = HashIO::new("savetest/versiontest".to_string());
+        let old = Old { a: 1, b: "keep".to_string() };
+        let old_hash = old.as_hash();
+        hash_io.put(&old).unwrap();
+        let upgraded: New = hash_io.get(&old_hash).unwrap();
+
+        // Old data is written back unchanged.
+        assert_eq!(old_hash, upgraded.as_hash());
+        let mut bytes = Vec::new();
+        upgraded.write_to(&mut bytes).unwrap();
+        let mut old_bytes = Vec::new();
+        old.write_to(&mut old_bytes).unwrap();
+        assert_eq!(old_bytes, bytes);
+
+        // A default value doesn't need the new version, so old readers
+        // still understand it.
+        let default = New { a: 1, b: "keep".to_string(), c: 7 };
+        assert_eq!(old_hash, default.as_hash());
+        let changed = New { a: 1, b: "keep".to_string(), c: 8 };
+        assert!(old_hash != changed.as_hash());
+    }
+
+    #[test]
+    fn schema_test() {
+        assert_eq!(vec![("a", "u8"), ("b", "String")], Old::schema());
+        assert_eq!(vec![("a", "u8"), ("b", "String"), ("c", "u8")], New::schema());
+    }
+
+    #[test]
+    fn too_new_test() {
+        let hash_io = HashIO::new("savetest/versiontest".to_string());
+        let new = New { a: 2, b: "too new".to_string(), c: 3 };
+        hash_io.put(&new).unwrap();
+        match hash_io.get::<Old>(&new.as_hash()) {
+            Err(HashIOError::VersionError(_)) => (),
+            res => panic!("Expected VersionError, got {:?}", res)
+        }
+
+        let mut bytes = Vec::new();
+        write_u32(99, &mut bytes).unwrap();
+        write_u8(1, &mut bytes).unwrap();
+        write_hash(&new.b.as_hash(), &mut bytes).unwrap();
+        let hash = Hash::hash_bytes(&bytes);
+        hash_io.put_raw(&hash, &bytes).unwrap();
+        match hash_io.get::<New>(&hash) {
+            Err(HashIOError::VersionError(_)) => (),
+            res => panic!("Expected VersionError, got {:?}", res)
+        }
+
+        // Containers only know HASHIO_FORMAT_VERSION
+        match hash_io.get::<Vec<String>>(&hash) {
+            Err(HashIOError::VersionError(_)) => (),
+            res => panic!("Expected VersionError, got {:?}", res)
+        }
+    }
+
+    #[test]
+    fn oldest_version_test() {
+        let hash_io = HashIO::new("savetest/versiontest".to_string());
+        let b = "oldest".to_string();
+        hash_io.put(&b).unwrap();
+
+        // Version 0 is the oldest version and is still read by all models.
+        let mut bytes = Vec::new();
+        write_u32(0, &mut bytes).unwrap();
+        write_u8(4, &mut bytes).unwrap();
+        write_hash(&b.as_hash(), &mut bytes).unwrap();
+        let hash = Hash::hash_bytes(&bytes);
+        hash_io.put_raw(&hash, &bytes).unwrap();
+        assert_eq!(Old { a: 4, b: b.clone() }, hash_io.get(&hash).unwrap());
+        assert_eq!(New { a: 4, b: b.clone(), c: 7 }, hash_io.get(&hash).unwrap());
+
+        let mut bytes = Vec::new();
+        write_u32(HASHIO_FORMAT_VERSION, &mut bytes).unwrap();
+        write_u32(1, &mut bytes).unwrap();
+        write_hash(&b.as_hash(), &mut bytes).unwrap();
+        let hash = Hash::hash_bytes(&bytes);
+        hash_io.put_raw(&hash, &bytes).unwrap();
+        assert_eq!(vec![b], hash_io.get::<Vec<String>>(&hash).unwrap());
+    }
+}
+
 #[cfg(test)]
 mod btreemaptest {
     use super::super::hash::*;
@@ -417,14 +1735,22 @@ mod btreemaptest {
         let a_2 = hash_io.get(&hash).unwrap();
         assert_eq!(a, a_2);
     }
+
+    #[test]
+    fn write_size_test() {
+        let mut a = BTreeMap::new();
+        a.insert("one".to_string(), "1".to_string());
+        let mut bytes = Vec::new();
+        let size = a.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+    }
 }
 
 impl<T> Writable for Vec<T>
     where T: Writable, T: Hashable {
     fn write_to<W: Write>(&self, write: &mut W) -> Result<usize, io::Error> {
-        try!(write_u32(0, write));
-        try!(write_u32(self.len() as u32, write));
-        let mut size: usize = 0;
+        let mut size = try!(write_u32(HASHIO_FORMAT_VERSION, write));
+        size += try!(write_u32(self.len() as u32, write));
         for value in self {
             size += try!(write_hash(&value.as_hash(), write));
         }
@@ -442,19 +1768,31 @@ impl<T> Hashable for Vec<T>
 impl<T> HashIOImpl<Vec<T>> for HashIO
     where HashIO: HashIOImpl<T>,
           T: Writable, T: Hashable {
-    fn store_hashable<W>(&self, hashable: &Vec<T>, write: &mut W) -> Result<(), HashIOError>
-        where W: Write {
+    fn store_childs(&self, hashable: &Vec<T>) -> Result<(), HashIOError> {
         for value in hashable {
             try!(self.put(value));
         }
+        Ok(())
+    }
+
+    fn store_hashable<W>(&self, hashable: &Vec<T>, write: &mut W) -> Result<(), HashIOError>
+        where W: Write {
         try!(hashable.write_to(write));
         Ok(())
     }
 
+    fn walk_childs<F>(&self, hashable: &Vec<T>, visit: &mut F) -> Result<(), HashIOError>
+        where F: FnMut(&Hash, &EntrySize) -> Result<bool, HashIOError> {
+        for value in hashable.iter() {
+            try!(self.walk(value, visit));
+        }
+        Ok(())
+    }
+
     fn receive_hashable<R>(&self, read: &mut R) -> Result<Vec<T>, HashIOError>
         where R: Read {
         let mut res = Vec::<T>::new();
-        try!(read_u32(read));
+        try!(expect_container_version(read));
         let entries = try!(read_u32(read));
         for _ in 0..entries {
             let value_hash = try!(read_hash(read));
@@ -464,3 +1802,260 @@ impl<T> HashIOImpl<Vec<T>> for HashIO
         Ok(res)
     }
 }
+
+impl<T, const N: usize> Writable for [T; N]
+    where T: Writable, T: Hashable {
+    fn write_to<W: Write>(&self, write: &mut W) -> Result<usize, io::Error> {
+        let mut size = try!(write_u32(HASHIO_FORMAT_VERSION, write));
+        for value in self.iter() {
+            size += try!(write_hash(&value.as_hash(), write));
+        }
+        Ok(size)
+    }
+}
+
+impl<T, const N: usize> Hashable for [T; N]
+    where [T; N]: Writable {
+    fn as_hash(&self) -> Hash {
+        self.writable_to_hash()
+    }
+}
+
+impl<T, const N: usize> HashIOImpl<[T; N]> for HashIO
+    where HashIO: HashIOImpl<T>,
+          T: Writable, T: Hashable {
+    fn store_childs(&self, hashable: &[T; N]) -> Result<(), HashIOError> {
+        for value in hashable.iter() {
+            try!(self.put(value));
+        }
+        Ok(())
+    }
+
+    fn store_hashable<W>(&self, hashable: &[T; N], write: &mut W) -> Result<(), HashIOError>
+        where W: Write {
+        try!(hashable.write_to(write));
+        Ok(())
+    }
+
+    fn walk_childs<F>(&self, hashable: &[T; N], visit: &mut F) -> Result<(), HashIOError>
+        where F: FnMut(&Hash, &EntrySize) -> Result<bool, HashIOError> {
+        for value in hashable.iter() {
+            try!(self.walk(value, visit));
+        }
+        Ok(())
+    }
+
+    fn receive_hashable<R>(&self, read: &mut R) -> Result<[T; N], HashIOError>
+        where R: Read {
+        let mut res = Vec::<T>::with_capacity(N);
+        try!(expect_container_version(read));
+        for _ in 0..N {
+            let value_hash = try!(read_hash(read));
+            let value = try!(self.get(&value_hash));
+            res.push(value);
+        }
+        <[T; N]>::try_from(res)
+            .map_err(|_| HashIOError::Undefined(format!("Expected {} array entries", N)))
+    }
+}
+
+#[cfg(test)]
+mod arraytest {
+    use super::super::hash::*;
+    use super::super::hashio::*;
+
+    #[test]
+    fn test() {
+        let hash_io = HashIO::new("savetest/arraytest".to_string());
+        let a = ["one".to_string(), "two".to_string(), "three".to_string()];
+        let hash = a.as_hash();
+        hash_io.put(&a).unwrap();
+        let a_2: [String; 3] = hash_io.get(&hash).unwrap();
+        assert_eq!(a, a_2);
+    }
+
+    #[test]
+    fn write_size_test() {
+        let a = ["one".to_string(), "two".to_string()];
+        let mut bytes = Vec::new();
+        let size = a.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+    }
+}
+
+macro_rules! hashio_tuple {
+    ($( $type_name:ident : $index:tt ),*) => {
+        impl<$($type_name),*> Writable for ($($type_name,)*)
+            where $($type_name: Writable + Hashable),* {
+            fn write_to<W: Write>(&self, write: &mut W) -> Result<usize, io::Error> {
+                let mut size = try!(write_u32(HASHIO_FORMAT_VERSION, write));
+                $( size += try!(write_hash(&self.$index.as_hash(), write)); )*
+                Ok(size)
+            }
+        }
+
+        impl<$($type_name),*> Hashable for ($($type_name,)*)
+            where ($($type_name,)*): Writable {
+            fn as_hash(&self) -> Hash {
+                self.writable_to_hash()
+            }
+        }
+
+        impl<$($type_name),*> HashIOImpl<($($type_name,)*)> for HashIO
+            where $(HashIO: HashIOImpl<$type_name>, $type_name: Writable + Hashable),* {
+            fn receive_hashable<R>(&self, read: &mut R) -> Result<($($type_name,)*), HashIOError>
+                where R: Read {
+                try!(expect_container_version(read));
+                Ok(($(
+                    {
+                        let hash_val = try!(read_hash(read));
+                        let value: $type_name = try!(self.get(&hash_val));
+                        value
+                    },
+                )*))
+            }
+
+            fn store_childs(&self, hashable: &($($type_name,)*)) -> Result<(), HashIOError> {
+                $( try!(self.put(&hashable.$index)); )*
+                Ok(())
+            }
+
+            fn walk_childs<F>(&self, hashable: &($($type_name,)*), visit: &mut F)
+                    -> Result<(), HashIOError>
+                where F: FnMut(&Hash, &EntrySize) -> Result<bool, HashIOError> {
+                $( try!(self.walk(&hashable.$index, visit)); )*
+                Ok(())
+            }
+
+            fn store_hashable<W>(&self, hashable: &($($type_name,)*), write: &mut W)
+                    -> Result<(), HashIOError>
+                where W: Write {
+                try!(hashable.write_to(write));
+                Ok(())
+            }
+        }
+    }
+}
+
+hashio_tuple!(A: 0, B: 1);
+hashio_tuple!(A: 0, B: 1, C: 2);
+
+#[cfg(test)]
+mod tupletest {
+    use super::super::hash::*;
+    use super::super::hashio::*;
+
+    #[test]
+    fn test() {
+        let hash_io = HashIO::new("savetest/tupletest".to_string());
+        let pair = ("one".to_string(), "two".to_string());
+        let triple = ("one".to_string(), "two".to_string(), "three".to_string());
+        hash_io.put(&pair).unwrap();
+        hash_io.put(&triple).unwrap();
+
+        let pair_2: (String, String) = hash_io.get(&pair.as_hash()).unwrap();
+        let triple_2: (String, String, String) = hash_io.get(&triple.as_hash()).unwrap();
+        assert_eq!(pair, pair_2);
+        assert_eq!(triple, triple_2);
+    }
+
+    #[test]
+    fn write_size_test() {
+        let pair = ("one".to_string(), "two".to_string());
+        let mut bytes = Vec::new();
+        let size = pair.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+    }
+}
+
+impl<T> Writable for BTreeSet<T>
+    where T: Writable, T: Hashable {
+    fn write_to<W: Write>(&self, write: &mut W) -> Result<usize, io::Error> {
+        let mut size = try!(write_u32(HASHIO_FORMAT_VERSION, write));
+        size += try!(write_u32(self.len() as u32, write));
+        for value in self {
+            size += try!(write_hash(&value.as_hash(), write));
+        }
+        Ok(size)
+    }
+}
+
+impl<T> Hashable for BTreeSet<T>
+    where BTreeSet<T>: Writable {
+    fn as_hash(&self) -> Hash {
+        self.writable_to_hash()
+    }
+}
+
+impl<T> HashIOImpl<BTreeSet<T>> for HashIO
+    where HashIO: HashIOImpl<T>,
+          T: Writable, T: Hashable,
+          T: Ord {
+    fn store_childs(&self, hashable: &BTreeSet<T>) -> Result<(), HashIOError> {
+        for value in hashable {
+            try!(self.put(value));
+        }
+        Ok(())
+    }
+
+    fn store_hashable<W>(&self, hashable: &BTreeSet<T>, write: &mut W) -> Result<(), HashIOError>
+        where W: Write {
+        try!(hashable.write_to(write));
+        Ok(())
+    }
+
+    fn walk_childs<F>(&self, hashable: &BTreeSet<T>, visit: &mut F) -> Result<(), HashIOError>
+        where F: FnMut(&Hash, &EntrySize) -> Result<bool, HashIOError> {
+        for value in hashable.iter() {
+            try!(self.walk(value, visit));
+        }
+        Ok(())
+    }
+
+    fn receive_hashable<R>(&self, read: &mut R) -> Result<BTreeSet<T>, HashIOError>
+        where R: Read {
+        let mut res = BTreeSet::<T>::new();
+        try!(expect_container_version(read));
+        let entries = try!(read_u32(read));
+        for _ in 0..entries {
+            let value_hash = try!(read_hash(read));
+            let value = try!(self.get(&value_hash));
+            res.insert(value);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod btreesettest {
+    use super::super::hash::*;
+    use super::super::hashio::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test() {
+        let hash_io = HashIO::new("savetest/btreesettest".to_string());
+        let mut a = BTreeSet::new();
+        a.insert("one".to_string());
+        a.insert("two".to_string());
+        a.insert("three".to_string());
+        let mut b = BTreeSet::new();
+        b.insert("three".to_string());
+        b.insert("one".to_string());
+        b.insert("two".to_string());
+        assert_eq!(a.as_hash(), b.as_hash());
+
+        hash_io.put(&a).unwrap();
+        let a_2: BTreeSet<String> = hash_io.get(&b.as_hash()).unwrap();
+        assert_eq!(a, a_2);
+    }
+
+    #[test]
+    fn write_size_test() {
+        let mut a = BTreeSet::new();
+        a.insert("one".to_string());
+        let mut bytes = Vec::new();
+        let size = a.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+    }
+}
@@ -3,8 +3,6 @@ extern crate byteorder;
 extern crate time;
 
 use std::io::{Read, Write};
-use self::crypto::sha3::Sha3;
-use self::crypto::digest::Digest;
 use self::byteorder::{BigEndian, ByteOrder};
 use std::io;
 
@@ -23,6 +21,16 @@ pub fn read_u8<R>(read: &mut R) -> Result<u8, io::Error> where R: Read {
     Ok(bytes[0])
 }
 
+pub fn write_i8<W>(i: i8, write: &mut W) -> Result<usize, io::Error> where W: Write {
+    write_u8(i as u8, write)
+}
+
+pub fn read_i8<R>(read: &mut R) -> Result<i8, io::Error> where R: Read {
+    let mut bytes = [0u8; 1];
+    try!(read.read_exact(&mut bytes));
+    Ok(bytes[0] as i8)
+}
+
 
 
 pub fn write_u32<W>(i: u32, write: &mut W) -> Result<usize, io::Error> where W: Write {
@@ -61,6 +69,120 @@ pub fn read_i16<R>(read: &mut R) -> Result<i16, io::Error> where R: Read {
     Ok(BigEndian::read_i16(&bytes))
 }
 
+/// Write an unsigned integer as LEB128 varint.
+///
+/// Small values take less space than with the fixed size functions:  values
+/// below 128 only need one byte.  This is a different encoding than write_u32,
+/// so it must not be mixed up with it.
+pub fn write_varint<W>(i: u64, write: &mut W) -> Result<usize, io::Error> where W: Write {
+    let mut value = i;
+    let mut size: usize = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            size += try!(write_u8(byte, write));
+            return Ok(size);
+        }
+        size += try!(write_u8(byte | 0x80, write));
+    }
+}
+
+/// Read a single byte and fail at the end of the input.
+///
+/// read_u8 returns 0 if nothing is left, which hides truncated data.
+fn read_byte<R>(read: &mut R) -> Result<u8, io::Error> where R: Read {
+    let mut bytes = [0u8; 1];
+    try!(read.read_exact(&mut bytes));
+    Ok(bytes[0])
+}
+
+/// Read an unsigned integer which was written by write_varint.
+pub fn read_varint<R>(read: &mut R) -> Result<u64, io::Error> where R: Read {
+    let mut result: u64 = 0;
+    let mut shift: u32 = 0;
+    loop {
+        if shift >= 64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "varint is too long"));
+        }
+        let byte = try!(read_byte(read));
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+        shift += 7;
+    }
+}
+
+/// Write an optional count as flag byte followed by a varint.
+pub fn write_option_usize<W>(i: Option<usize>, write: &mut W) -> Result<usize, io::Error> where W: Write {
+    match i {
+        None => write_u8(0, write),
+        Some(i) => {
+            let size = try!(write_u8(1, write));
+            Ok(size + try!(write_varint(i as u64, write)))
+        }
+    }
+}
+
+/// Read an optional count which was written by write_option_usize.
+pub fn read_option_usize<R>(read: &mut R) -> Result<Option<usize>, io::Error> where R: Read {
+    match try!(read_byte(read)) {
+        0 => Ok(None),
+        1 => Ok(Some(try!(read_varint(read)) as usize)),
+        flag => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                   format!("Invalid option flag {}", flag)))
+    }
+}
+
+pub fn write_u32_var<W>(i: u32, write: &mut W) -> Result<usize, io::Error> where W: Write {
+    write_varint(i as u64, write)
+}
+
+pub fn read_u32_var<R>(read: &mut R) -> Result<u32, io::Error> where R: Read {
+    let value = try!(read_varint(read));
+    if value > u32::max_value() as u64 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "varint exceeds u32"));
+    }
+    Ok(value as u32)
+}
+
+/// Write a string with a varint length prefix.
+pub fn write_string_var<W>(string: &str, write: &mut W) -> Result<usize, io::Error> where W: Write {
+    let bytes = string.as_bytes();
+    let mut size = try!(write_varint(bytes.len() as u64, write));
+    try!(write.write_all(bytes));
+    size += bytes.len();
+    Ok(size)
+}
+
+/// Read a string which was written by write_string_var.
+pub fn read_string_var<R>(read: &mut R) -> Result<String, io::Error> where R: Read {
+    let len = try!(read_varint(read));
+    let bytes = try!(read_bytes(read, len as usize));
+    String::from_utf8(bytes)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Write a list of hashes with a varint count prefix.
+pub fn write_hashes_var<W>(hashes: &[Hash], write: &mut W) -> Result<usize, io::Error> where W: Write {
+    let mut size = try!(write_varint(hashes.len() as u64, write));
+    for hash in hashes {
+        size += try!(write_hash(hash, write));
+    }
+    Ok(size)
+}
+
+/// Read a list of hashes which was written by write_hashes_var.
+pub fn read_hashes_var<R>(read: &mut R) -> Result<Vec<Hash>, io::Error> where R: Read {
+    let len = try!(read_varint(read));
+    let mut res = Vec::new();
+    for _ in 0..len {
+        res.push(try!(read_hash(read)));
+    }
+    Ok(res)
+}
+
 pub fn write_f32<W>(f: f32, write: &mut W) -> Result<usize, io::Error> where W: Write {
     let mut bytes = [0u8; 4];
     BigEndian::write_f32(&mut bytes, f);
@@ -73,6 +195,19 @@ pub fn read_f32<R>(read: &mut R) -> Result<f32, io::Error> where R: Read {
     Ok(BigEndian::read_f32(&bytes))
 }
 
+pub fn write_f64<W>(f: f64, write: &mut W) -> Result<usize, io::Error> where W: Write {
+    let mut bytes = [0u8; 8];
+    BigEndian::write_f64(&mut bytes, f);
+    try!(write.write_all(&bytes));
+    Ok(bytes.len())
+}
+
+pub fn read_f64<R>(read: &mut R) -> Result<f64, io::Error> where R: Read {
+    let mut bytes = [0u8; 8];
+    try!(read.read_exact(&mut bytes));
+    Ok(BigEndian::read_f64(&bytes))
+}
+
 
 /// Write itself to any write trait.
 ///
@@ -84,13 +219,36 @@ pub trait Writable {
         let mut write: Vec<u8> = Vec::new();
         self.write_to(&mut write)
             .expect("Writing to a vec should not cause any issues");
-        let data = write.as_slice();
-        let mut hasher = Sha3::sha3_256();
-        let mut hash_bytes = [0u8; 32];
-        hasher.input(data);
-        hasher.result(&mut hash_bytes);
-        let hash = Hash::Sha3(hash_bytes);
-        hash
+        Hash::hash_bytes(write.as_slice())
+    }
+
+    /// Generate the same hash as writable_to_hash without buffering.
+    ///
+    /// The output of write_to is directly passed to the hasher, so no copy
+    /// of the serialized data is kept in memory.  Use this for large values.
+    fn stream_hash(&self) -> Hash {
+        let mut write = HashWriter {
+            hasher: Hasher::new()
+        };
+        self.write_to(&mut write)
+            .expect("Writing to a hasher should not cause any issues");
+        write.hasher.finish()
+    }
+}
+
+/// Write adapter which passes everything written to a Hasher.
+struct HashWriter {
+    hasher: Hasher
+}
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        self.hasher.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        Ok(())
     }
 }
 
@@ -150,23 +308,38 @@ pub fn usize_to_u32_bytes(x: usize) -> [u8; 4] {
     res
 }
 
+/// Maximal number of bytes read_bytes reserves up front.
+///
+/// A corrupt length must not cause a huge allocation, so longer reads let
+/// the buffer grow while the data actually arrives.
+const READ_BYTES_INITIAL_CAPACITY: usize = 64 * 1024;
+
+/// Read exactly n bytes.
+///
+/// # Errors
+/// Returns an UnexpectedEof error if the reader ends before n bytes were read.
 pub fn read_bytes(reader: &mut Read, n: usize) -> Result<Vec<u8>, io::Error> {
-    let mut buffer: [u8; 1] = [0; 1];
-    let mut res: Vec<u8> = Vec::with_capacity(n);
-    for _ in 0..n {
-        try!(reader.read(&mut buffer));
-        res.push(buffer[0]);
+    let mut res: Vec<u8> = Vec::with_capacity(n.min(READ_BYTES_INITIAL_CAPACITY));
+    try!(reader.take(n as u64).read_to_end(&mut res));
+    if res.len() != n {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                  format!("Expected {} bytes but got {}", n, res.len())));
     }
     Ok(res)
 }
 
+/// Write the hash with a leading byte which identifies its variant.
+///
+/// Returns the number of written bytes including the identifier, so it is
+/// 1 for Hash::None and 33 for a Sha3 hash.
 pub fn write_hash<W>(hash: &Hash, write: &mut W) -> Result<usize, io::Error> where W: Write {
     let bytes = hash.get_bytes();
     try!(write_u8(match hash {
         &Hash::None => 0,
         &Hash::Sha3(_) => 1
     }, write));
-    write.write(&*bytes)
+    try!(write.write_all(&*bytes));
+    Ok(1 + bytes.len())
 }
 
 pub fn read_hash<R>(read: &mut R) -> Result<Hash, io::Error> where R: Read {
@@ -174,7 +347,7 @@ pub fn read_hash<R>(read: &mut R) -> Result<Hash, io::Error> where R: Read {
     match identifier {
         1 => {
             let mut bytes = [0u8; 32];
-            try!(read.read(&mut bytes));
+            try!(read.read_exact(&mut bytes));
             Ok(Hash::Sha3(bytes))
         }
         _ => Ok(Hash::None)
@@ -222,4 +395,213 @@ pub fn read_tm<R>(read: &mut R) -> Result<Tm, io::Error> where R: Read {
         tm_utcoff: utcoff,
         tm_nsec: nsec
     })
-}
\ No newline at end of file
+}
+
+/// Format a Tm as RFC3339 string like 2017-07-14T02:40:00Z.
+///
+/// The date and time are written as they are stored in the Tm, together
+/// with its UTC offset.  Nanoseconds are dropped.
+pub fn tm_to_rfc3339(tm: &Tm) -> String {
+    format!("{}", tm.rfc3339())
+}
+
+/// Parse a RFC3339 string like tm_to_rfc3339 writes it.
+///
+/// The offset is kept in tm_utcoff and the date and time are not converted
+/// to UTC, so a task which floors the result to a day gets the day which is
+/// written in the string.  Week day and year day are calculated and the
+/// daylight saving flag is unknown (-1), like in the task models.
+pub fn rfc3339_to_tm(s: &str) -> Result<Tm, ParseError> {
+    let mut tm = try!(strptime(s, "%Y-%m-%dT%H:%M:%S%z"));
+    let utcoff = tm.tm_utcoff;
+    tm.tm_utcoff = 0;
+    let utc = at_utc(tm.to_timespec());
+    tm.tm_wday = utc.tm_wday;
+    tm.tm_yday = utc.tm_yday;
+    tm.tm_isdst = -1;
+    tm.tm_utcoff = utcoff;
+    Ok(tm)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_bytes_test() {
+        let bytes: Vec<u8> = (0..200000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(bytes, read_bytes(&mut &bytes[..], bytes.len()).unwrap());
+        assert_eq!(Vec::<u8>::new(), read_bytes(&mut &bytes[..], 0).unwrap());
+
+        // A length beyond the available data must fail instead of allocating
+        // the whole length.
+        let err = read_bytes(&mut &bytes[..10], usize::max_value() / 2).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn read_hash_test() {
+        let hash = Hash::hash_bytes(b"complete");
+        let mut bytes: Vec<u8> = Vec::new();
+        write_hash(&hash, &mut bytes).unwrap();
+        assert_eq!(hash, read_hash(&mut &bytes[..]).unwrap());
+
+        // Identifier and only a part of the hash
+        let err = read_hash(&mut &bytes[..10]).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn write_hash_size_test() {
+        for hash in [Hash::None, Hash::hash_bytes(b"some")].iter() {
+            let mut bytes: Vec<u8> = Vec::new();
+            let size = write_hash(hash, &mut bytes).unwrap();
+            assert_eq!(1 + hash.byte_len(), bytes.len());
+            assert_eq!(bytes.len(), size);
+        }
+    }
+
+    #[test]
+    fn rfc3339_test() {
+        let tm = rfc3339_to_tm("2017-07-14T02:40:00Z").unwrap();
+        assert_eq!(117, tm.tm_year);
+        assert_eq!(6, tm.tm_mon);
+        assert_eq!(14, tm.tm_mday);
+        assert_eq!(2, tm.tm_hour);
+        assert_eq!(40, tm.tm_min);
+        assert_eq!(0, tm.tm_sec);
+        assert_eq!(0, tm.tm_utcoff);
+        assert_eq!(5, tm.tm_wday);
+        assert_eq!(194, tm.tm_yday);
+        assert_eq!(Timespec::new(1500000000, 0), tm.to_timespec());
+
+        // The offset is kept and the day is not moved to UTC.
+        let tm = rfc3339_to_tm("2017-07-14T00:30:00+02:00").unwrap();
+        assert_eq!(14, tm.tm_mday);
+        assert_eq!(7200, tm.tm_utcoff);
+        assert_eq!("2017-07-14T00:30:00+02:00", tm_to_rfc3339(&tm));
+
+        assert!(rfc3339_to_tm("2017-07-14").is_err());
+        assert!(rfc3339_to_tm("not a time").is_err());
+    }
+
+    #[test]
+    fn rfc3339_round_trip_test() {
+        for s in ["2017-07-14T02:40:00Z", "1999-12-31T23:59:59Z",
+                  "2020-02-29T12:00:00-05:30"].iter() {
+            let tm = rfc3339_to_tm(s).unwrap();
+            assert_eq!(*s, tm_to_rfc3339(&tm));
+            assert_eq!(tm, rfc3339_to_tm(&tm_to_rfc3339(&tm)).unwrap());
+        }
+    }
+
+    #[test]
+    fn option_usize_test() {
+        for value in [None, Some(0), Some(3), Some(100000)].iter() {
+            let mut bytes: Vec<u8> = Vec::new();
+            let size = write_option_usize(*value, &mut bytes).unwrap();
+            assert_eq!(bytes.len(), size);
+            assert_eq!(*value, read_option_usize(&mut &bytes[..]).unwrap());
+        }
+        let err = read_option_usize(&mut &[2u8][..]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        for bytes in vec![vec![], vec![1u8]] {
+            let err = read_option_usize(&mut bytes.as_slice()).unwrap_err();
+            assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+        }
+    }
+
+    #[test]
+    fn stream_hash_test() {
+        let big: String = "0123456789abcdef".repeat(256 * 1024);
+        assert_eq!(big.writable_to_hash(), big.stream_hash());
+    }
+
+    #[test]
+    fn i8_test() {
+        for i in [::std::i8::MIN, ::std::i8::MAX, 0, -1, 5, -5].iter() {
+            let mut bytes: Vec<u8> = Vec::new();
+            assert_eq!(1, write_i8(*i, &mut bytes).unwrap());
+            assert_eq!(*i, read_i8(&mut &bytes[..]).unwrap());
+        }
+        let mut bytes: Vec<u8> = Vec::new();
+        write_i8(-1, &mut bytes).unwrap();
+        assert_eq!(vec![0xFF], bytes);
+        assert!(read_i8(&mut &[0u8; 0][..]).is_err());
+    }
+
+    fn f64_round_trip(f: f64) -> f64 {
+        let mut bytes: Vec<u8> = Vec::new();
+        assert_eq!(8, write_f64(f, &mut bytes).unwrap());
+        assert_eq!(8, bytes.len());
+        read_f64(&mut &bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn f64_test() {
+        for f in [0.0, 0.1, 1234.5678, -42.25, ::std::f64::MAX, ::std::f64::MIN].iter() {
+            assert_eq!(*f, f64_round_trip(*f));
+        }
+        let nan = ::std::f64::NAN;
+        assert!(f64_round_trip(nan).is_nan());
+        assert_eq!(nan.to_bits(), f64_round_trip(nan).to_bits());
+        assert!(read_f64(&mut &[0u8; 4][..]).is_err());
+    }
+
+    fn varint_bytes(i: u64) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        let size = write_varint(i, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+        bytes
+    }
+
+    #[test]
+    fn varint_test() {
+        assert_eq!(vec![0x00], varint_bytes(0));
+        assert_eq!(vec![0x7f], varint_bytes(127));
+        assert_eq!(vec![0x80, 0x01], varint_bytes(128));
+        assert_eq!(vec![0xff, 0x7f], varint_bytes(16383));
+        assert_eq!(vec![0x80, 0x80, 0x01], varint_bytes(16384));
+        for i in vec![0, 1, 127, 128, 16383, 16384, u32::max_value() as u64, u64::max_value()] {
+            let bytes = varint_bytes(i);
+            assert_eq!(i, read_varint(&mut bytes.as_slice()).unwrap());
+        }
+    }
+
+    #[test]
+    fn varint_too_long_test() {
+        let bytes = [0xffu8; 11];
+        assert!(read_varint(&mut &bytes[..]).is_err());
+        let mut u64_bytes: Vec<u8> = Vec::new();
+        write_varint(u64::max_value(), &mut u64_bytes).unwrap();
+        assert!(read_u32_var(&mut u64_bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn varint_truncated_test() {
+        for bytes in vec![vec![], vec![0x80], vec![0xff, 0xff]] {
+            let err = read_varint(&mut bytes.as_slice()).unwrap_err();
+            assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+        }
+    }
+
+    #[test]
+    fn string_var_test() {
+        let mut bytes: Vec<u8> = Vec::new();
+        write_string_var("short", &mut bytes).unwrap();
+        assert_eq!(6, bytes.len());
+        assert_eq!("short", read_string_var(&mut bytes.as_slice()).unwrap());
+
+        let mut bytes: Vec<u8> = Vec::new();
+        write_string_var("", &mut bytes).unwrap();
+        assert_eq!("", read_string_var(&mut bytes.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn hashes_var_test() {
+        let hashes = vec![Hash::hash_bytes(b"one"), Hash::None, Hash::hash_bytes(b"two")];
+        let mut bytes: Vec<u8> = Vec::new();
+        write_hashes_var(&hashes, &mut bytes).unwrap();
+        assert_eq!(hashes, read_hashes_var(&mut bytes.as_slice()).unwrap());
+    }
+}
@@ -1,4 +1,14 @@
 //! Task management library to successfully complete tasks
+
+// Renamed because the log module of this crate uses the same name.
+#[macro_use]
+extern crate log as logging;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
 #[macro_use]
 pub mod hash;
 #[macro_use]
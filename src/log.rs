@@ -83,6 +83,31 @@ pub trait Log {
     /// Throws an error if an entry of the hash was not found.
     fn get(&self, hash: Hash) -> Result<Self::Item, LogError>;
 
+    /// Borrow the entry of the given hash without cloning it.
+    ///
+    /// Only logs which keep their entries in memory can support this.
+    ///
+    /// # Errors
+    /// Throws an error if an entry of the hash was not found.  By default,
+    /// it always throws an error because the log doesn't support borrowing.
+    fn get_ref(&self, hash: Hash) -> Result<&Self::Item, LogError> {
+        Err(LogError::CustomError(format!("Entry {} can't be borrowed from this log",
+                                          hash.as_string())))
+    }
+
+    /// Calculate the hash of an entry with the given parent.
+    ///
+    /// verify_log uses it to check the stored hashes.  By default, the
+    /// hash of the entry hash is used for the first entry and otherwise
+    /// the entry hash combined with the parent hash.
+    fn entry_hash(&self, entry: &Self::Item, parent_hash: Option<Hash>) -> Hash {
+        let hash = entry.as_hash();
+        match parent_hash {
+            None => hash.as_hash(),
+            Some(parent_hash) => hash.hash_with(parent_hash)
+        }
+    }
+
     /// Verify if hash is in the log
     fn has_hash(&self, hash: Hash) -> bool {
         match self.parent_hash(hash) {
@@ -119,7 +144,8 @@ pub trait Log {
 /// ```
 pub struct LogIteratorRef<'a, L: Log<Item=T> + 'a, T: Hashable> {
     log: &'a L,
-    hash: Option<Hash>
+    hash: Option<Hash>,
+    stop: Option<Hash>
 }
 
 impl<'a, L: Log<Item=T>, T: Hashable + 'a> LogIteratorRef<'a, L, T> {
@@ -127,7 +153,23 @@ impl<'a, L: Log<Item=T>, T: Hashable + 'a> LogIteratorRef<'a, L, T> {
     pub fn from_log(log: &'a L) -> LogIteratorRef<'a, L, T> {
         LogIteratorRef {
             log: log,
-            hash: log.head_hash()
+            hash: log.head_hash(),
+            stop: None
+        }
+    }
+
+    /// Returns an iterator over the entries from to_inclusive back to
+    /// from_exclusive.
+    ///
+    /// Iteration starts at to_inclusive and stops before from_exclusive is
+    /// reached or at the first entry of the log.  With the hash of the last
+    /// synced entry as from_exclusive, it returns all newer entries.
+    pub fn range(log: &'a L, from_exclusive: Option<Hash>,
+                 to_inclusive: Hash) -> LogIteratorRef<'a, L, T> {
+        LogIteratorRef {
+            log: log,
+            hash: if from_exclusive == Some(to_inclusive) { None } else { Some(to_inclusive) },
+            stop: from_exclusive
         }
     }
 }
@@ -141,6 +183,9 @@ impl<'a, L: Log<Item=T>, T: Hashable + 'a> Iterator for LogIteratorRef<'a, L, T>
             Some(hash) => {
                 let value = self.log.get(hash).ok();
                 self.hash = self.log.parent_hash(hash).unwrap_or(None);
+                if self.hash.is_some() && self.hash == self.stop {
+                    self.hash = None;
+                }
                 value
             }
         }
@@ -202,6 +247,50 @@ impl<'a, L: Log<Item=T>, T: Hashable + 'a> Iterator for LogIteratorHash<'a, L, T
 
 
 
+/// Iterate over the elements of any log, the oldest entry first.
+///
+/// Entries only know their parents, so the hashes of the whole chain are
+/// collected when the iterator is created.  Because of this, the iterator
+/// needs O(n) memory.
+///
+/// # Examples
+/// ```
+/// use tbd::log::*;
+/// let mut log = DefaultLog::<String>::default();
+///
+/// log.push("str1".to_string());
+/// log.push("str2".to_string());
+/// log.push("str3".to_string());
+///
+/// let res: Vec<String> = LogIteratorForward::from_log(&log).collect();
+///
+/// assert_eq!(vec!["str1", "str2", "str3"], res);
+/// ```
+pub struct LogIteratorForward<'a, L: Log<Item=T> + 'a, T: Hashable> {
+    log: &'a L,
+    hashes: Vec<Hash>
+}
+
+impl<'a, L: Log<Item=T>, T: Hashable + 'a> LogIteratorForward<'a, L, T> {
+    /// Returns an iterator for the given Log which starts at the oldest entry.
+    pub fn from_log(log: &'a L) -> LogIteratorForward<'a, L, T> {
+        LogIteratorForward {
+            log: log,
+            hashes: LogIteratorHash::from_log(log).collect()
+        }
+    }
+}
+
+impl<'a, L: Log<Item=T>, T: Hashable + 'a> Iterator for LogIteratorForward<'a, L, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match self.hashes.pop() {
+            None => None,
+            Some(hash) => self.log.get(hash).ok()
+        }
+    }
+}
 
 
 
@@ -210,6 +299,7 @@ impl<'a, L: Log<Item=T>, T: Hashable + 'a> Iterator for LogIteratorHash<'a, L, T
 #[derive(Debug, PartialEq)]
 pub enum LogError {
     EntryNotFound(Hash),
+    CustomError(String),
     Unknown
 }
 
@@ -218,6 +308,7 @@ impl fmt::Display for LogError {
         match *self {
             LogError::EntryNotFound(hash) => write!(f, "Entry not found for hash: {}",
                                           hash.as_string()),
+            LogError::CustomError(ref msg) => write!(f, "Log error: {}", msg),
             LogError::Unknown => write!(f, "Unknown log error")
         }
     }
@@ -227,6 +318,7 @@ impl Error for LogError {
     fn description(&self) -> &str {
         match *self {
             LogError::EntryNotFound(_) => "Entry for hash not found",
+            LogError::CustomError(ref msg) => msg,
             LogError::Unknown => "Unknown log error"
         }
     }
@@ -254,6 +346,38 @@ pub struct DefaultLog<T: Hashable + Clone> {
 }
 
 impl<T: Hashable + Clone> DefaultLog<T> {
+    /// Create a log and push the entries, the oldest entry first.
+    ///
+    /// # Examples
+    /// ```
+    /// use tbd::log::*;
+    /// let log = DefaultLog::from_entries(vec!["str1".to_string(), "str2".to_string()]);
+    ///
+    /// assert_eq!(Some("str2".to_string()), log.iter().next());
+    /// ```
+    pub fn from_entries(entries: Vec<T>) -> DefaultLog<T> {
+        let mut log = DefaultLog::default();
+        for entry in entries {
+            log.push(entry);
+        }
+        log
+    }
+
+    /// Take the entries out of the log, the oldest entry first.
+    ///
+    /// Only entries which are reachable from the head are returned, so
+    /// entries which were dropped by reset_head are not part of it.
+    pub fn into_entries(mut self) -> Vec<T> {
+        let mut res = Vec::new();
+        let mut hash = self.head;
+        while let Some(entry) = hash.and_then(|hash| self.entries.remove(&hash)) {
+            hash = entry.parent_hash;
+            res.push(entry.entry);
+        }
+        res.reverse();
+        res
+    }
+
     /// Get the iterator for the entries.
     pub fn iter(&self) -> LogIteratorRef<DefaultLog<T>, T> {
         LogIteratorRef::from_log(self)
@@ -264,6 +388,14 @@ impl<T: Hashable + Clone> DefaultLog<T> {
         LogIteratorHash::from_log(self)
     }
 
+    /// Get an iterator which borrows the entries instead of cloning them.
+    pub fn iter_ref(&self) -> DefaultLogIteratorBorrow<'_, T> {
+        DefaultLogIteratorBorrow {
+            entries: &self.entries,
+            hash: self.head
+        }
+    }
+
     /// Set load function called when an entry was not found.
     pub fn with_load_fn(mut self, load_fn: Box<Fn(Hash) -> Option<DefaultLogEntry<T>>>) -> DefaultLog<T> {
         self.load = load_fn;
@@ -288,11 +420,7 @@ impl<T: Hashable + Clone> Log for DefaultLog<T> {
     ///
     /// Returns the hash value for the entry.
     fn push(&mut self, t: T) -> Hash {
-        let entry_hash = t.as_hash();
-        let hash = match self.head {
-            None => entry_hash.as_hash(),
-            Some(head_hash) => entry_hash.hash_with(head_hash)
-        };
+        let hash = self.entry_hash(&t, self.head);
         let log_entry = DefaultLogEntry {
             entry: t,
             parent_hash: self.head
@@ -327,6 +455,14 @@ impl<T: Hashable + Clone> Log for DefaultLog<T> {
         }
     }
 
+    /// Borrow the entry with the given hash.
+    fn get_ref(&self, hash: Hash) -> Result<&Self::Item, LogError> {
+        match self.entries.get(&hash) {
+            None => Result::Err(LogError::EntryNotFound(hash)),
+            Some(ref entry) => Ok(&entry.entry)
+        }
+    }
+
     fn reset_head(&mut self, hash: &Hash) -> Result<(), LogError> {
         match self.entries.get(&hash) {
             None => Result::Err(LogError::EntryNotFound(hash.clone())),
@@ -338,6 +474,29 @@ impl<T: Hashable + Clone> Log for DefaultLog<T> {
     }
 }
 
+/// Iterator over borrowed entries of a DefaultLog, the newest entry first.
+///
+/// Created by DefaultLog::iter_ref.
+pub struct DefaultLogIteratorBorrow<'a, T: Hashable + Clone + 'a> {
+    entries: &'a BTreeMap<Hash, DefaultLogEntry<T>>,
+    hash: Option<Hash>
+}
+
+impl<'a, T: Hashable + Clone + 'a> Iterator for DefaultLogIteratorBorrow<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        match self.hash {
+            None => None,
+            Some(hash) => {
+                let entry = self.entries.get(&hash);
+                self.hash = entry.and_then(|entry| entry.parent_hash);
+                entry.map(|entry| &entry.entry)
+            }
+        }
+    }
+}
+
 impl<T: Hashable + Clone> Default for DefaultLog<T> {
     fn default() -> Self {
         DefaultLog {
@@ -353,20 +512,26 @@ impl<T: Hashable + Clone> Default for DefaultLog<T> {
 
 #[derive(PartialEq, Debug)]
 pub enum LogVerifyFailure<T> {
+    /// The hash of an entry doesn't match.
+    ///
+    /// depth is the position of the entry counted from the head, so the
+    /// head has depth 0.  All entries with a higher depth were verified.
     LogHashFailure {
         t: T,
         actual_hash: Hash,
-        expected_hash: Hash
+        expected_hash: Hash,
+        depth: usize
     },
     LogError(LogError)
 }
 
-fn gen_verify_failure<T>(t: T, act: Hash, exp: Hash)
+fn gen_verify_failure<T>(t: T, act: Hash, exp: Hash, depth: usize)
                            -> LogVerifyFailure<T> {
     LogVerifyFailure::LogHashFailure {
         t: t,
         actual_hash: act,
-        expected_hash: exp
+        expected_hash: exp,
+        depth: depth
     }
 }
 
@@ -376,23 +541,19 @@ fn gen_verify_failure<T>(t: T, act: Hash, exp: Hash)
 pub fn verify_log<L, T>(log: &L) -> Option<LogVerifyFailure<T>>
         where L: Log<Item=T>, T: Hashable {
     let hashes: Vec<Hash> = LogIteratorHash::from_log(log).collect();
-    for hash in hashes.iter().rev() {
+    for (depth, hash) in hashes.iter().enumerate().rev() {
         let parent_hash_result = log.parent_hash(*hash);
         let entry = match log.get(*hash) {
             Err(err) => return Some(LogVerifyFailure::LogError(err)),
             Ok(hash) => hash
         };
-        let entry_hash = entry.as_hash();
         let expected_hash = match parent_hash_result {
-            Ok(parent_hash_option) => match parent_hash_option {
-                None => entry_hash.as_hash(),
-                Some(parent_hash) => entry_hash.hash_with(parent_hash)
-            },
+            Ok(parent_hash_option) => log.entry_hash(&entry, parent_hash_option),
             Err(err) => return Some(LogVerifyFailure::LogError(err))
         };
 
         if *hash != expected_hash {
-            return Some(gen_verify_failure(entry, *hash, expected_hash));
+            return Some(gen_verify_failure(entry, *hash, expected_hash, depth));
         }
     }
     None
@@ -415,3 +576,107 @@ pub fn rebuild_log<L, T>(log: &L) -> Result<L, LogError>
     }
     Ok(res)
 }
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn verify_depth_test() {
+        let mut log = DefaultLog::<String>::default();
+        let mut hashes = Vec::new();
+        for entry in ["one", "two", "three", "four"].iter() {
+            hashes.push(log.push(entry.to_string()));
+        }
+        assert_eq!(None, verify_log(&log));
+
+        // Manipulate the second oldest entry
+        log.entries.get_mut(&hashes[1]).unwrap().entry = "manipulated".to_string();
+        match verify_log(&log) {
+            Some(LogVerifyFailure::LogHashFailure { t, actual_hash, depth, .. }) => {
+                assert_eq!("manipulated", t);
+                assert_eq!(hashes[1], actual_hash);
+                assert_eq!(2, depth);
+            },
+            res => panic!("Expected LogHashFailure, got {:?}", res)
+        }
+    }
+
+    #[test]
+    fn get_ref_test() {
+        let mut log = DefaultLog::<String>::default();
+        let hash = log.push("entry".to_string());
+        let entry: &String = log.get_ref(hash).unwrap();
+        assert_eq!(&log.get(hash).unwrap(), entry);
+        let unknown = Hash::hash_bytes(b"unknown");
+        assert_eq!(Err(LogError::EntryNotFound(unknown)), log.get_ref(unknown));
+    }
+
+    #[test]
+    fn iter_ref_test() {
+        let mut log = DefaultLog::<String>::default();
+        assert_eq!(None, log.iter_ref().next());
+        for entry in ["one", "two", "three"].iter() {
+            log.push(entry.to_string());
+        }
+        let borrowed: Vec<&String> = log.iter_ref().collect();
+        let cloned: Vec<String> = log.iter().collect();
+        assert_eq!(vec!["three", "two", "one"], borrowed);
+        assert_eq!(cloned.iter().collect::<Vec<&String>>(), borrowed);
+    }
+
+    #[test]
+    fn from_entries_test() {
+        let entries: Vec<String> = ["one", "two", "three"].iter()
+            .map(|entry| entry.to_string()).collect();
+        let log = DefaultLog::from_entries(entries.clone());
+        assert_eq!(None, verify_log(&log));
+        let mut pushed = DefaultLog::<String>::default();
+        for entry in entries.iter() {
+            pushed.push(entry.clone());
+        }
+        assert_eq!(pushed.head_hash(), log.head_hash());
+        assert_eq!(entries, log.into_entries());
+
+        assert_eq!(None, DefaultLog::<String>::from_entries(Vec::new()).head_hash());
+        assert_eq!(Vec::<String>::new(), DefaultLog::<String>::default().into_entries());
+
+        // Entries after the head are not returned.
+        let mut log = DefaultLog::from_entries(entries.clone());
+        let second = log.hash_iter().nth(1).unwrap();
+        log.reset_head(&second).unwrap();
+        assert_eq!(entries[..2].to_vec(), log.into_entries());
+    }
+
+    #[test]
+    fn range_test() {
+        let mut log = DefaultLog::<String>::default();
+        let mut hashes = Vec::new();
+        for entry in ["one", "two", "three", "four"].iter() {
+            hashes.push(log.push(entry.to_string()));
+        }
+        let range = |from: Option<usize>, to: usize| -> Vec<String> {
+            LogIteratorRef::range(&log, from.map(|i| hashes[i]), hashes[to]).collect()
+        };
+        assert_eq!(vec!["four", "three", "two", "one"], range(None, 3));
+        assert_eq!(vec!["four", "three"], range(Some(1), 3));
+        assert_eq!(vec!["three", "two"], range(Some(0), 2));
+        assert_eq!(vec!["two"], range(Some(0), 1));
+        assert_eq!(Vec::<String>::new(), range(Some(2), 2));
+        assert_eq!(vec!["one"], range(None, 0));
+
+        // A from which is not an ancestor runs to the first entry.
+        assert_eq!(vec!["two", "one"], range(Some(3), 1));
+    }
+
+    #[test]
+    fn custom_error_test() {
+        let err = LogError::CustomError("broken chain".to_string());
+        assert_eq!("Log error: broken chain", format!("{}", err));
+        #[allow(deprecated)]
+        let description = err.description().to_string();
+        assert_eq!("broken chain", description);
+    }
+}
@@ -64,7 +64,7 @@
 extern crate time;
 extern crate rand;
 
-use self::time::{Duration, Tm};
+use self::time::{Duration, Timespec, Tm};
 use std::collections::BTreeMap;
 use io::*;
 use hashio::*;
@@ -73,23 +73,35 @@ use hash::*;
 use std::io::{Read, Write};
 use std::error;
 use std::fmt;
+use std::cmp::Ordering;
 
 /// Base task type
+///
+/// The estimate was added in version 1.  Tasks stored before get an
+/// estimate of 0 minutes when they are loaded, and tasks without estimate
+/// are still stored as version 0 so their hash doesn't change.
 tbd_model!(Task, [
         [factor: f32, write_f32, read_f32]
     ], [
         [title: String],
         [description: String]
+    ], 1, [
+        [estimate_minutes: i32, write_i32, read_i32, 1, 0]
     ]);
 
 
 
 /// A task which got activated.
+///
+/// The progress was added in version 1.  Active tasks stored before get a
+/// progress of 0.0 when they are loaded.
 tbd_model!(ActiveTask, [
         [start: Tm, write_tm, read_tm],
         [due: Tm, write_tm, read_tm]
     ], [
         [task: Task]
+    ], 1, [
+        [progress: f32, write_f32, read_f32, 1, 0.0]
     ]);
 
 
@@ -106,11 +118,17 @@ tbd_model!(PooledTask, [
 
 
 /// Overall state of the tasks
+///
+/// The limit of active tasks was added in version 1.  States stored before
+/// have no limit when they are loaded, and states without a limit are still
+/// stored as version 0 so their hash doesn't change.
 tbd_model!(TaskStat, [
         [ref_tm: Tm, write_tm, read_tm]
     ], [
         [active: BTreeMap<String, ActiveTask>],
         [pool: BTreeMap<String, PooledTask>]
+    ], 1, [
+        [max_active: Option<usize>, write_option_usize, read_option_usize, 1, None]
     ]);
 
 
@@ -119,12 +137,95 @@ impl PooledTask {
     fn title_string(&self) -> String {
         self.task.title.to_string()
     }
+
+    /// Estimated effort of the task in minutes.
+    pub fn estimate_minutes(&self) -> i32 {
+        self.task.estimate_minutes
+    }
+
+    /// Days until the task can be picked again, relative to ref_tm.
+    ///
+    /// Partial days are rounded up.  Returns 0 if the task is not cooling
+    /// down.
+    pub fn cooldown_remaining(&self, ref_tm: &Tm) -> i64 {
+        let remaining = self.cooling_until - *ref_tm;
+        if remaining <= Duration::zero() {
+            0
+        } else {
+            (remaining.num_seconds() + 86399) / 86400
+        }
+    }
+}
+
+/// Collects the attributes of a new pooled task.
+///
+/// Attributes which are not set use defaults: a factor and propability of
+/// 1.0, no cool down and one day until the task is due.  Pass it to
+/// TaskStatTrait::add_pooled to add the task.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PooledTaskBuilder {
+    pub title: String,
+    pub description: String,
+    pub factor: f32,
+    pub propability: f32,
+    pub cool_down: i16,
+    pub due_days: i16
+}
+
+impl PooledTaskBuilder {
+    pub fn new(title: String) -> PooledTaskBuilder {
+        PooledTaskBuilder {
+            title: title,
+            description: String::new(),
+            factor: 1.0,
+            propability: 1.0,
+            cool_down: 0,
+            due_days: 1
+        }
+    }
+
+    pub fn with_description(mut self, description: String) -> PooledTaskBuilder {
+        self.description = description;
+        self
+    }
+
+    pub fn with_factor(mut self, factor: f32) -> PooledTaskBuilder {
+        self.factor = factor;
+        self
+    }
+
+    pub fn with_propability(mut self, propability: f32) -> PooledTaskBuilder {
+        self.propability = propability;
+        self
+    }
+
+    pub fn with_cool_down(mut self, cool_down: i16) -> PooledTaskBuilder {
+        self.cool_down = cool_down;
+        self
+    }
+
+    pub fn with_due_days(mut self, due_days: i16) -> PooledTaskBuilder {
+        self.due_days = due_days;
+        self
+    }
 }
 
 impl ActiveTask {
     fn title_string(&self) -> String {
         self.task.title.to_string()
     }
+
+    /// Estimated effort of the task in minutes.
+    pub fn estimate_minutes(&self) -> i32 {
+        self.task.estimate_minutes
+    }
+
+    /// Full days from ref_tm until the task is due.
+    ///
+    /// The result is negative if the task is overdue.
+    pub fn days_until_due(&self, ref_tm: &Tm) -> i64 {
+        (self.due - *ref_tm).num_days()
+    }
 }
 
 pub trait TaskStatTrait {
@@ -139,6 +240,31 @@ pub trait TaskStatTrait {
     fn mark_done(&mut self, title: String) -> Result<ActiveTask, Self::Error>;
     fn all_actives(&self) -> Result<Vec<ActiveTask>, Self::Error>;
     fn all_pooled(&self) -> Result<Vec<PooledTask>, Self::Error>;
+
+    /// Add the pooled task described by the builder.
+    fn add_pooled(&mut self, builder: PooledTaskBuilder) -> Result<PooledTask, Self::Error> {
+        self.add_pooled_task(builder.title, builder.description, builder.factor,
+                             builder.propability, builder.cool_down, builder.due_days)
+    }
+}
+
+/// Latest time a task date may have, 9999-12-31T23:59:59Z.
+const MAX_DATE_SEC: i64 = 253402300799;
+/// Earliest time a task date may have, 0000-01-01T00:00:00Z.
+const MIN_DATE_SEC: i64 = -62167219200;
+
+/// Add days to tm if the result stays a four digit year.
+///
+/// Returns None instead of overflowing the time calculations, which would
+/// panic, or producing dates which can't be written as RFC3339.
+fn checked_add_days(tm: Tm, days: i64) -> Option<Tm> {
+    let sec = days.checked_mul(24 * 60 * 60)
+        .and_then(|secs| tm.to_timespec().sec.checked_add(secs));
+    match sec {
+        Some(sec) if (MIN_DATE_SEC..=MAX_DATE_SEC).contains(&sec) =>
+            Some(tm + Duration::days(days)),
+        _ => None
+    }
 }
 
 /// Floor to the day if tm and remove time zone information
@@ -156,6 +282,25 @@ fn floor_tm_day(tm: &mut time::Tm) {
     tm.tm_utcoff = 0;
 }
 
+/// Recompute the fields of tm which are derived from its date.
+///
+/// The week day, year day and daylight saving flag depend on how a Tm was
+/// created.  Tms for the same point in time would otherwise compare and hash
+/// differently.  Used for every Tm which enters a task model.
+fn canonicalize_tm(tm: &mut time::Tm) {
+    let mut date = *tm;
+    floor_tm_day(&mut date);
+    let date = time::at_utc(date.to_timespec());
+    tm.tm_wday = date.tm_wday;
+    tm.tm_yday = date.tm_yday;
+    tm.tm_isdst = -1;
+}
+
+/// Format the date of tm as YYYY-MM-DD.
+fn format_day(tm: &time::Tm) -> String {
+    format!("{:04}-{:02}-{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday)
+}
+
 /// Task stat implementation
 impl TaskStat {
     /// Generate a new and empty task stat
@@ -163,12 +308,208 @@ impl TaskStat {
         TaskStat {
             active: BTreeMap::new(),
             pool: BTreeMap::new(),
-            ref_tm: time::now()
+            ref_tm: time::now(),
+            max_active: None
+        }
+    }
+
+    /// Generate a new and empty task stat which refers to the day of ref_tm.
+    ///
+    /// Useful to control the current day in tests and simulations.
+    pub fn with_ref_tm(mut ref_tm: Tm) -> TaskStat {
+        floor_tm_day(&mut ref_tm);
+        canonicalize_tm(&mut ref_tm);
+        TaskStat {
+            active: BTreeMap::new(),
+            pool: BTreeMap::new(),
+            ref_tm: ref_tm,
+            max_active: None
+        }
+    }
+
+    /// Limit the number of active tasks, None removes the limit.
+    ///
+    /// Tasks which are already active stay active if there are more than
+    /// the limit.  Only new tasks are blocked until there are less.
+    pub fn set_max_active(&mut self, max_active: Option<usize>) {
+        self.max_active = max_active;
+    }
+
+    /// Returns true if no more tasks can be activated because of max_active.
+    pub fn is_active_limit_reached(&self) -> bool {
+        match self.max_active {
+            Some(max_active) => self.active.len() >= max_active,
+            None => false
         }
     }
 
+    /// Set ref_tm to the start of the current day.
+    ///
+    /// All cool down and due calculations are relative to ref_tm.  TaskLog
+    /// calls this before every modification, so the state always refers to
+    /// the day the modification happened.
     pub fn update_ref_tm(&mut self) {
         self.ref_tm = time::now();
+        floor_tm_day(&mut self.ref_tm);
+        canonicalize_tm(&mut self.ref_tm);
+    }
+
+    /// Activate the pooled task with the given title.
+    ///
+    /// Returns None if there is no pooled task with the title or if it
+    /// can't be activated because it is already active, cooling down,
+    /// the limit of active tasks is reached or its due date would overflow.
+    pub fn activate_named(&mut self, title: String) -> Option<ActiveTask> {
+        if self.is_active_limit_reached() {
+            return None;
+        }
+        let p_task = match self.pool.get(&title) {
+            Some(p_task) => p_task.clone(),
+            None => return None
+        };
+        if !self.can_activate(&p_task) {
+            return None;
+        }
+        self.activate_p_task(&p_task).ok()
+    }
+
+    /// Remove all pooled tasks for which predicate returns true.
+    ///
+    /// Returns the removed tasks ordered by title.
+    pub fn purge_pooled<F: Fn(&PooledTask) -> bool>(&mut self, predicate: F) -> Vec<PooledTask> {
+        let titles: Vec<String> = self.pool.values()
+            .filter(|p_task| predicate(p_task))
+            .map(|p_task| p_task.title_string())
+            .collect();
+        titles.iter().filter_map(|title| self.pool.remove(title)).collect()
+    }
+
+    /// Remove all pooled tasks which are never activated randomly.
+    pub fn purge_never_picked(&mut self) -> Vec<PooledTask> {
+        self.purge_pooled(|p_task| p_task.propability == 0.0)
+    }
+
+    /// Tell if the pooled task with the given title could be activated now.
+    ///
+    /// Returns None if there is no pooled task with the title.
+    pub fn activation_status(&self, title: &str) -> Option<ActivationStatus> {
+        self.pool.get(title).map(|p_task| self.p_task_activation_status(p_task))
+    }
+
+    /// Add the tasks of other to this state.
+    ///
+    /// Active and pooled tasks are merged independently.  If both states
+    /// contain a task with the same title, strategy decides which one is
+    /// kept.  KeepNewest compares the start of active tasks and the end of
+    /// the cool down of pooled tasks and keeps this state's task on a tie.
+    pub fn merge(&mut self, other: &TaskStat, strategy: MergeStrategy) {
+        for (title, a_task) in other.active.iter() {
+            let take_other = match self.active.get(title) {
+                None => true,
+                Some(own) => strategy.take_other(&own.start, &a_task.start)
+            };
+            if take_other {
+                self.active.insert(title.clone(), a_task.clone());
+            }
+        }
+        for (title, p_task) in other.pool.iter() {
+            let take_other = match self.pool.get(title) {
+                None => true,
+                Some(own) => strategy.take_other(&own.cooling_until, &p_task.cooling_until)
+            };
+            if take_other {
+                self.pool.insert(title.clone(), p_task.clone());
+            }
+        }
+    }
+
+    /// Store the state in the HashIO at path and return its hash.
+    pub fn save(&self, path: &str) -> Result<Hash, HashIOError> {
+        HashIO::new(path.to_string()).put(self)
+    }
+
+    /// Load the state of the hash from the HashIO at path.
+    pub fn load(path: &str, hash: &Hash) -> Result<TaskStat, HashIOError> {
+        HashIO::new(path.to_string()).get(hash)
+    }
+
+    /// Add a new active task unless one with the same title is active.
+    ///
+    /// # Errors
+    /// Returns TaskExists if a task with the title is already active.
+    pub fn add_active_task_checked(&mut self, title: String, description: String,
+                                   factor: f32, due_days: i16) -> Result<ActiveTask, TaskStatError> {
+        if self.active.contains_key(&title) {
+            return Err(TaskStatError::TaskExists(title));
+        }
+        self.add_active_task(title, description, factor, due_days)
+    }
+
+    /// Sum of the estimated minutes of all active tasks.
+    pub fn total_estimate_active(&self) -> i64 {
+        self.active.values()
+            .map(|a_task| a_task.estimate_minutes() as i64)
+            .sum()
+    }
+
+    /// Set the estimate of the active and the pooled task with the title.
+    ///
+    /// Fails with TaskNotFound if there is neither an active nor a pooled
+    /// task and with InvalidValue if the estimate is negative.
+    pub fn set_estimate_minutes(&mut self, title: &str, estimate_minutes: i32)
+            -> Result<(), TaskStatError> {
+        if estimate_minutes < 0 {
+            return Err(TaskStatError::InvalidValue(
+                format!("Estimate of {} must not be negative, got {}",
+                        title, estimate_minutes)));
+        }
+        let mut found = false;
+        if let Some(a_task) = self.active.get_mut(title) {
+            a_task.task.estimate_minutes = estimate_minutes;
+            found = true;
+        }
+        if let Some(p_task) = self.pool.get_mut(title) {
+            p_task.task.estimate_minutes = estimate_minutes;
+            found = true;
+        }
+        if found {
+            Ok(())
+        } else {
+            Err(TaskStatError::TaskNotFound(title.to_string()))
+        }
+    }
+
+    /// Titles of all active tasks in sorted order.
+    ///
+    /// Cheaper than all_actives if only the titles are needed.
+    pub fn active_titles(&self) -> Vec<String> {
+        self.active.keys().cloned().collect()
+    }
+
+    /// All active tasks, the most recently started first.
+    ///
+    /// Tasks started on the same day are ordered by title.
+    pub fn actives_by_start(&self) -> Vec<ActiveTask> {
+        let mut res: Vec<ActiveTask> = self.active.values().cloned().collect();
+        res.sort_by(|a, b| b.start.cmp(&a.start));
+        res
+    }
+
+    /// Titles of all pooled tasks in sorted order.
+    pub fn pooled_titles(&self) -> Vec<String> {
+        self.pool.keys().cloned().collect()
+    }
+
+    /// Activate tasks as if it was the day of as_of.
+    ///
+    /// Sets ref_tm to the day of as_of and runs the normal activation, so
+    /// cool downs and due dates are relative to that day.
+    pub fn activate_at<R: rand::Rng>(&mut self, rng: &mut R,
+                                     as_of: Tm) -> Result<Vec<ActiveTask>, TaskStatError> {
+        self.ref_tm = as_of;
+        floor_tm_day(&mut self.ref_tm);
+        canonicalize_tm(&mut self.ref_tm);
+        self.activate(rng)
     }
 
 
@@ -193,55 +534,336 @@ impl TaskStat {
         now < task_limit
     }
 
-    fn can_activate(&self, p_task: &PooledTask) -> bool {
-        let is_active = self.is_p_task_active(p_task);
-        let is_cooldown = self.is_p_task_cooling_down(p_task);
-        if is_active {
-            println!("{} is already active", p_task.title_string());
+    fn p_task_activation_status(&self, p_task: &PooledTask) -> ActivationStatus {
+        if self.is_p_task_active(p_task) {
+            ActivationStatus::AlreadyActive
+        } else if self.is_p_task_cooling_down(p_task) {
+            ActivationStatus::CoolingDown { until: p_task.cooling_until }
+        } else {
+            ActivationStatus::Eligible
         }
-        if is_cooldown {
-            println!("{} is cooling down", p_task.title_string());
-        }
-        return !(is_active || is_cooldown)
     }
 
-    fn p_to_a_task(&self, p_task: &PooledTask) -> ActiveTask {
-        let mut finish_day = self.ref_tm + Duration::days(p_task.due_days as i64);
+    fn can_activate(&self, p_task: &PooledTask) -> bool {
+        self.p_task_activation_status(p_task) == ActivationStatus::Eligible
+    }
+
+    fn p_to_a_task(&self, p_task: &PooledTask) -> Result<ActiveTask, TaskStatError> {
+        let mut finish_day = match checked_add_days(self.ref_tm, p_task.due_days as i64) {
+            Some(finish_day) => finish_day,
+            None => return Err(TaskStatError::DateOverflow(p_task.task.title.clone()))
+        };
         floor_tm_day(&mut finish_day);
-        ActiveTask {
+        canonicalize_tm(&mut finish_day);
+        let mut start = self.ref_tm;
+        canonicalize_tm(&mut start);
+        Ok(ActiveTask {
             task: p_task.task.clone(),
-            start: self.ref_tm,
-            due: finish_day
-        }
+            start: start,
+            due: finish_day,
+            progress: 0.0
+        })
     }
 
-    fn activate_p_task(&mut self, p_task: &PooledTask) -> ActiveTask {
-        let a_task = self.p_to_a_task(p_task);
+    fn activate_p_task(&mut self, p_task: &PooledTask) -> Result<ActiveTask, TaskStatError> {
+        let a_task = try!(self.p_to_a_task(p_task));
         self.active.insert(a_task.title_string(), a_task.clone());
+        Ok(a_task)
+    }
+
+
+    /// Remove the active task with the given title and return it.
+    ///
+    /// Also restarts the cool down of the pooled task with the same title.
+    /// Returns None if there is no active task with this title.
+    pub fn complete_task(&mut self, title: String) -> Option<ActiveTask> {
+        self.complete_task_with_cooldown(title, None)
+    }
+
+    /// Complete the active task and override the cool down of its pooled task.
+    ///
+    /// With Some(days), the pooled task cools down for the given days instead
+    /// of its own cool down, Some(0) makes it available immediately.  None
+    /// behaves like mark_done.  Returns false if the task was not active.
+    pub fn mark_done_with_cooldown(&mut self, title: String,
+                                   cooldown_override: Option<i16>) -> bool {
+        self.complete_task_with_cooldown(title, cooldown_override).is_some()
+    }
+
+    fn complete_task_with_cooldown(&mut self, title: String,
+                                   cooldown_override: Option<i16>) -> Option<ActiveTask> {
+        let a_task = self.active.remove(&title);
+        if a_task.is_some() {
+            self.renew_p_task(&title, cooldown_override);
+        }
         a_task
     }
 
+    /// Put a completed task back to the active tasks.
+    ///
+    /// The task keeps its original start and due date.  Returns None if a
+    /// task with the same title is already active.
+    pub fn reopen_task(&mut self, a_task: ActiveTask) -> Option<ActiveTask> {
+        let title = a_task.title_string();
+        if self.active.contains_key(&title) {
+            return None;
+        }
+        self.active.insert(title, a_task.clone());
+        Some(a_task)
+    }
+
+    /// Rename a task and keep its state.
+    ///
+    /// The active and the pooled task with the title are both renamed, so
+    /// an activated pooled task stays linked to its pool entry.
+    ///
+    /// # Errors
+    /// Returns TaskNotFound if there is no task with the old title and
+    /// TaskExists if the new title is already used.
+    pub fn rename_task(&mut self, old: String, new: String) -> Result<(), TaskStatError> {
+        if !self.active.contains_key(&old) && !self.pool.contains_key(&old) {
+            return Err(TaskStatError::TaskNotFound(old));
+        }
+        if self.active.contains_key(&new) || self.pool.contains_key(&new) {
+            return Err(TaskStatError::TaskExists(new));
+        }
+        if let Some(mut a_task) = self.active.remove(&old) {
+            a_task.task.title = new.clone();
+            self.active.insert(new.clone(), a_task);
+        }
+        if let Some(mut p_task) = self.pool.remove(&old) {
+            p_task.task.title = new.clone();
+            self.pool.insert(new, p_task);
+        }
+        Ok(())
+    }
+
+    /// Set the progress of an active task.
+    ///
+    /// The progress is clamped to the range from 0.0 to 1.0 and NaN counts
+    /// as 0.0.  Returns the updated task or None if there is no active task
+    /// with this title.
+    pub fn set_progress(&mut self, title: String, progress: f32) -> Option<ActiveTask> {
+        let progress = if progress.is_nan() { 0.0 } else { progress.clamp(0.0, 1.0) };
+        match self.active.get_mut(&title) {
+            Some(a_task) => {
+                a_task.progress = progress;
+                Some(a_task.clone())
+            },
+            None => None
+        }
+    }
+
+    /// Full days from ref_tm until the active task with the title is due.
+    ///
+    /// Returns None if there is no active task with this title.
+    pub fn days_until_due(&self, title: &str) -> Option<i64> {
+        self.active.get(title).map(|a_task| a_task.days_until_due(&self.ref_tm))
+    }
+
+    /// Urgency of an active task which is used by prioritized_actives.
+    ///
+    /// The urgency is `factor / (days_left + 1)` where `days_left` is the
+    /// number of full days from ref_tm until the due date of the task.
+    /// Overdue tasks count as `days_left = 0` so they are weighted by their
+    /// factor like tasks which are due today.
+    pub fn urgency(&self, a_task: &ActiveTask) -> f32 {
+        let days_left = a_task.days_until_due(&self.ref_tm);
+        let days_left = if days_left < 0 { 0 } else { days_left };
+        a_task.task.factor / (days_left + 1) as f32
+    }
+
+    /// Get all active tasks, the most urgent task first.
+    ///
+    /// The order is defined by the urgency function.  Tasks with the same
+    /// urgency are ordered by their due date and then by their title.
+    pub fn prioritized_actives(&self) -> Vec<ActiveTask> {
+        let mut res: Vec<ActiveTask> = self.active.values().cloned().collect();
+        res.sort_by(|a, b| {
+            self.urgency(b).partial_cmp(&self.urgency(a))
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.due.cmp(&b.due))
+        });
+        res
+    }
+
+    /// Complete all active tasks with the given titles.
+    ///
+    /// Returns the tasks which were active and got removed.  Titles without
+    /// an active task are ignored.
+    pub fn mark_done_many(&mut self, titles: &[String]) -> Vec<ActiveTask> {
+        let mut res = Vec::new();
+        for title in titles {
+            if let Some(a_task) = self.complete_task(title.clone()) {
+                res.push(a_task);
+            }
+        }
+        res
+    }
+
+    /// Generate a human readable report of all tasks.
+    ///
+    /// Active tasks are sorted by their due date and show the days left
+    /// relative to ref_tm.  Overdue tasks are marked as overdue.  Pooled
+    /// tasks show if they can be picked or are cooling down.
+    pub fn report(&self) -> String {
+        let mut today = self.ref_tm;
+        floor_tm_day(&mut today);
+        let mut res = String::new();
+
+        res.push_str("Active tasks:\n");
+        let mut a_tasks: Vec<&ActiveTask> = self.active.values().collect();
+        a_tasks.sort_by(|a, b| a.due.cmp(&b.due));
+        for a_task in a_tasks {
+            let days_left = a_task.days_until_due(&today);
+            let status = if days_left < 0 {
+                format!("overdue by {} days", -days_left)
+            } else if days_left == 0 {
+                "due today".to_string()
+            } else {
+                format!("{} days left", days_left)
+            };
+            res.push_str(&format!("  {}: due {} ({})\n", a_task.task.title,
+                                  format_day(&a_task.due), status));
+        }
+
+        res.push_str("Pooled tasks:\n");
+        for p_task in self.pool.values() {
+            let status = if self.is_p_task_cooling_down(p_task) {
+                format!("cooling down until {}", format_day(&p_task.cooling_until))
+            } else {
+                "available".to_string()
+            };
+            res.push_str(&format!("  {}: {}\n", p_task.task.title, status));
+        }
+        res
+    }
+
+    /// Compare this state with a newer one.
+    ///
+    /// Tasks are matched by their title.  Tasks which only exist in other
+    /// are added, tasks which only exist in self are removed and tasks which
+    /// exist in both but differ are modified.
+    pub fn diff(&self, other: &TaskStat) -> TaskStatDiff {
+        let mut res = TaskStatDiff::default();
+        for (title, a_task) in self.active.iter() {
+            match other.active.get(title) {
+                None => res.removed_actives.push(a_task.clone()),
+                Some(other_task) => if a_task != other_task {
+                    res.modified_actives.push((a_task.clone(), other_task.clone()));
+                }
+            }
+        }
+        for (title, a_task) in other.active.iter() {
+            if !self.active.contains_key(title) {
+                res.added_actives.push(a_task.clone());
+            }
+        }
+        for (title, p_task) in self.pool.iter() {
+            match other.pool.get(title) {
+                None => res.removed_pooled.push(p_task.clone()),
+                Some(other_task) => if p_task != other_task {
+                    res.modified_pooled.push((p_task.clone(), other_task.clone()));
+                }
+            }
+        }
+        for (title, p_task) in other.pool.iter() {
+            if !self.pool.contains_key(title) {
+                res.added_pooled.push(p_task.clone());
+            }
+        }
+        res
+    }
 
-    fn renew_p_task(&mut self, title: &String) {
+    fn renew_p_task(&mut self, title: &String, cooldown_override: Option<i16>) {
         match self.pool.get_mut(title) {
             Some(p_task) => {
-                p_task.cooling_until = self.ref_tm +
-                    Duration::days(p_task.cool_down as i64)}
+                let cool_down = cooldown_override.unwrap_or(p_task.cool_down);
+                // A cool down beyond the supported dates never ends.
+                p_task.cooling_until = checked_add_days(self.ref_tm, cool_down as i64)
+                    .unwrap_or_else(|| {
+                        let sec = if cool_down < 0 { MIN_DATE_SEC } else { MAX_DATE_SEC };
+                        time::at_utc(Timespec::new(sec, 0))
+                    });
+                canonicalize_tm(&mut p_task.cooling_until);
+            }
             None => ()
         }
     }
 }
 
+/// Differences between two task states, created by TaskStat::diff.
+///
+/// Modified tasks are stored as pair of the old and the new task.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TaskStatDiff {
+    pub added_actives: Vec<ActiveTask>,
+    pub removed_actives: Vec<ActiveTask>,
+    pub modified_actives: Vec<(ActiveTask, ActiveTask)>,
+    pub added_pooled: Vec<PooledTask>,
+    pub removed_pooled: Vec<PooledTask>,
+    pub modified_pooled: Vec<(PooledTask, PooledTask)>
+}
+
+impl TaskStatDiff {
+    /// Returns true if both states contain the same tasks.
+    pub fn is_empty(&self) -> bool {
+        self.added_actives.is_empty() && self.removed_actives.is_empty() &&
+            self.modified_actives.is_empty() && self.added_pooled.is_empty() &&
+            self.removed_pooled.is_empty() && self.modified_pooled.is_empty()
+    }
+}
+
+/// Decides which task is kept if both merged states contain the title.
+///
+/// See TaskStat::merge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeStrategy {
+    KeepSelf,
+    KeepOther,
+    KeepNewest
+}
+
+impl MergeStrategy {
+    fn take_other(&self, own_tm: &Tm, other_tm: &Tm) -> bool {
+        match *self {
+            MergeStrategy::KeepSelf => false,
+            MergeStrategy::KeepOther => true,
+            MergeStrategy::KeepNewest => other_tm > own_tm
+        }
+    }
+}
+
+/// Whether a pooled task can be activated, see TaskStat::activation_status.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActivationStatus {
+    Eligible,
+    AlreadyActive,
+    CoolingDown { until: Tm }
+}
+
 #[derive(Debug)]
 pub enum TaskStatError {
-    TaskNotFound(String)
+    TaskNotFound(String),
+    TaskExists(String),
+    InvalidValue(String),
+    ActiveLimitReached(usize),
+    DateOverflow(String)
 }
 
 impl fmt::Display for TaskStatError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             TaskStatError::TaskNotFound(ref title) =>
-                write!(f, "Task not found: {}", title)
+                write!(f, "Task not found: {}", title),
+            TaskStatError::TaskExists(ref title) =>
+                write!(f, "Task already exists: {}", title),
+            TaskStatError::InvalidValue(ref msg) =>
+                write!(f, "Invalid value: {}", msg),
+            TaskStatError::ActiveLimitReached(max_active) =>
+                write!(f, "Limit of {} active tasks reached", max_active),
+            TaskStatError::DateOverflow(ref title) =>
+                write!(f, "Due date of {} is out of range", title)
         }
     }
 }
@@ -249,7 +871,11 @@ impl fmt::Display for TaskStatError {
 impl error::Error for TaskStatError {
     fn description(&self) -> &str {
         match *self {
-            TaskStatError::TaskNotFound(_) => "TaskNotFound"
+            TaskStatError::TaskNotFound(_) => "TaskNotFound",
+            TaskStatError::TaskExists(_) => "TaskExists",
+            TaskStatError::InvalidValue(_) => "InvalidValue",
+            TaskStatError::ActiveLimitReached(_) => "ActiveLimitReached",
+            TaskStatError::DateOverflow(_) => "DateOverflow"
         }
     }
 }
@@ -269,28 +895,51 @@ impl TaskStatTrait for TaskStat {
             }
         }
         for p_task in insert_tasks {
-            result.push(self.activate_p_task(&p_task));
+            if self.is_active_limit_reached() {
+                break;
+            }
+            // A task which can't be activated must not block the others.
+            match self.activate_p_task(&p_task) {
+                Ok(a_task) => result.push(a_task),
+                Err(err) => warn!("Skipping activation of {}: {}", p_task.task.title, err)
+            }
         }
         Ok(result)
     }
 
     /// Generate a new task and add it to the active list
+    ///
+    /// An active task with the same title is replaced, including its start
+    /// and due date.  Use add_active_task_checked to prevent this.  Fails
+    /// with ActiveLimitReached if a new task would exceed max_active and
+    /// with DateOverflow if the due date is out of the supported range.
     fn add_active_task(&mut self,
                            title: String,
                            description: String,
                            factor: f32,
                            due_days: i16) -> Result<ActiveTask, Self::Error> {
-        floor_tm_day(&mut self.ref_tm);
-        let duration = Duration::days(due_days as i64);
-        let due = self.ref_tm + duration;
+        if !self.active.contains_key(&title) && self.is_active_limit_reached() {
+            return Err(TaskStatError::ActiveLimitReached(self.max_active.unwrap_or(0)));
+        }
+        let mut ref_tm = self.ref_tm;
+        floor_tm_day(&mut ref_tm);
+        canonicalize_tm(&mut ref_tm);
+        let mut due = match checked_add_days(ref_tm, due_days as i64) {
+            Some(due) => due,
+            None => return Err(TaskStatError::DateOverflow(title))
+        };
+        canonicalize_tm(&mut due);
+        self.ref_tm = ref_tm;
         let a_task = ActiveTask {
             task: Task {
                 title: title,
                 description: description,
-                factor: factor
+                factor: factor,
+                estimate_minutes: 0
             },
             start: self.ref_tm,
-            due: due
+            due: due,
+            progress: 0.0
         };
         self.active.insert(a_task.task.title.clone(), a_task.clone());
         Ok(a_task)
@@ -299,12 +948,29 @@ impl TaskStatTrait for TaskStat {
     fn add_pooled_task(&mut self, title: String, description: String,
                        factor: f32, propability: f32,
                        cool_down: i16, due_days: i16) -> Result<PooledTask, Self::Error> {
+        if factor.is_nan() || factor < 0.0 || factor.is_infinite() {
+            return Err(TaskStatError::InvalidValue(
+                format!("Factor of {} must be a non negative number, got {}", title, factor)));
+        }
+        if propability.is_nan() || propability < 0.0 {
+            return Err(TaskStatError::InvalidValue(
+                format!("Propability of {} must be a non negative number, got {}",
+                        title, propability)));
+        }
+        let propability = if propability > 1.0 {
+            warn!("Propability of {} is {}, using 1.0", title, propability);
+            1.0
+        } else {
+            propability
+        };
         floor_tm_day(&mut self.ref_tm);
+        canonicalize_tm(&mut self.ref_tm);
         let p_task = PooledTask {
             task: Task {
                 title: title,
                 description: description,
-                factor: factor
+                factor: factor,
+                estimate_minutes: 0
             },
             propability: propability,
             cool_down: cool_down,
@@ -316,12 +982,9 @@ impl TaskStatTrait for TaskStat {
     }
 
     fn mark_done(&mut self, title: String) -> Result<ActiveTask, Self::Error> {
-        match self.active.remove(&title) {
+        match self.complete_task(title.clone()) {
             None => Err(TaskStatError::TaskNotFound(title)),
-            Some(a_task) => {
-                self.renew_p_task(&title);
-                Ok(a_task)
-            }
+            Some(a_task) => Ok(a_task)
         }
     }
 
@@ -343,6 +1006,88 @@ impl TaskStatTrait for TaskStat {
 }
 
 
+/// JSON support for the task models behind the serde feature.
+///
+/// The models are generated by tbd_model!, so they are described by remote
+/// definitions here.  Tms are written as RFC 3339 strings.  The hashio
+/// format stays the format on disk.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::*;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+    mod tm_format {
+        use super::super::time::Tm;
+        use super::super::canonicalize_tm;
+        use io::{tm_to_rfc3339, rfc3339_to_tm};
+        use serde::{Deserialize, Deserializer, Serializer};
+        use serde::de::Error;
+
+        pub fn serialize<S: Serializer>(tm: &Tm, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&tm_to_rfc3339(tm))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Tm, D::Error> {
+            let tm_str = try!(String::deserialize(deserializer));
+            let mut tm = try!(rfc3339_to_tm(&tm_str)
+                .map_err(|err| D::Error::custom(format!("Invalid time {}: {}", tm_str, err))));
+            canonicalize_tm(&mut tm);
+            Ok(tm)
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(remote = "Task")]
+    struct TaskDef {
+        factor: f32,
+        title: String,
+        description: String,
+        #[serde(default)]
+        estimate_minutes: i32
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(remote = "ActiveTask")]
+    struct ActiveTaskDef {
+        #[serde(with = "tm_format")]
+        start: Tm,
+        #[serde(with = "tm_format")]
+        due: Tm,
+        task: Task,
+        progress: f32
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(remote = "PooledTask")]
+    struct PooledTaskDef {
+        propability: f32,
+        cool_down: i16,
+        due_days: i16,
+        #[serde(with = "tm_format")]
+        cooling_until: Tm,
+        task: Task
+    }
+
+    macro_rules! serde_for_remote {
+        ($model_name:ident, $def_name:ident) => {
+            impl Serialize for $model_name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    $def_name::serialize(self, serializer)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $model_name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$model_name, D::Error> {
+                    $def_name::deserialize(deserializer)
+                }
+            }
+        }
+    }
+
+    serde_for_remote!(Task, TaskDef);
+    serde_for_remote!(ActiveTask, ActiveTaskDef);
+    serde_for_remote!(PooledTask, PooledTaskDef);
+}
 
 
 #[cfg(test)]
@@ -350,7 +1095,9 @@ mod tests {
     use super::*;
     use super::rand;
     use super::rand::Rng;
+    use super::time::Duration;
     use std::collections::BTreeMap;
+    use std::fs::remove_dir_all;
 
     struct TestRand {
         i: usize,
@@ -435,4 +1182,763 @@ mod tests {
         assert_eq!(true, task_stat.mark_done("task a".to_string()).is_ok());
         assert_eq!(false, task_stat.mark_done("task b".to_string()).is_ok());
     }
+
+    #[test]
+    fn mark_done_many_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.add_active_task("task a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.add_active_task("task b".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.add_active_task("task c".to_string(), "".to_string(), 1.0, 3).unwrap();
+        let done = task_stat.mark_done_many(&["task a".to_string(), "task x".to_string(),
+                                             "task c".to_string()]);
+        assert_eq!(2, done.len());
+        assert_eq!("task a", done[0].task.title);
+        assert_eq!("task c", done[1].task.title);
+        assert_eq!(vec!["task b".to_string()], task_stat.active.keys().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rename_task_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        let a_task = task_stat.add_active_task("active".to_string(), "a".to_string(),
+                                               1.0, 3).unwrap();
+        task_stat.add_pooled_task("pooled".to_string(), "p".to_string(),
+                                  1.0, 0.5, 3, 2).unwrap();
+
+        task_stat.rename_task("active".to_string(), "renamed active".to_string()).unwrap();
+        assert!(!task_stat.active.contains_key("active"));
+        let renamed = &task_stat.active["renamed active"];
+        assert_eq!("renamed active", renamed.task.title);
+        assert_eq!(a_task.due, renamed.due);
+        assert_eq!("a", renamed.task.description);
+
+        task_stat.activate_named("pooled".to_string()).unwrap();
+        task_stat.rename_task("pooled".to_string(), "renamed pooled".to_string()).unwrap();
+        assert_eq!(vec!["renamed pooled".to_string()], task_stat.pooled_titles());
+        assert_eq!("renamed pooled", task_stat.pool["renamed pooled"].task.title);
+        assert_eq!(Some(ActivationStatus::AlreadyActive),
+                   task_stat.activation_status("renamed pooled"));
+    }
+
+    #[test]
+    fn rename_task_error_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        task_stat.add_active_task("a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.add_pooled_task("b".to_string(), "".to_string(), 1.0, 0.5, 3, 2).unwrap();
+        let before = task_stat.clone();
+
+        match task_stat.rename_task("missing".to_string(), "c".to_string()) {
+            Err(TaskStatError::TaskNotFound(ref title)) => assert_eq!("missing", title),
+            res => panic!("Expected TaskNotFound, got {:?}", res)
+        }
+        match task_stat.rename_task("a".to_string(), "b".to_string()) {
+            Err(TaskStatError::TaskExists(ref title)) => assert_eq!("b", title),
+            res => panic!("Expected TaskExists, got {:?}", res)
+        }
+        assert_eq!(before, task_stat);
+    }
+
+    #[test]
+    fn reopen_task_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.add_active_task("task a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        let done = task_stat.mark_done("task a".to_string()).unwrap();
+        assert_eq!(0, task_stat.all_actives().unwrap().len());
+
+        assert_eq!(Some(done.clone()), task_stat.reopen_task(done.clone()));
+        assert_eq!(vec![done.clone()], task_stat.all_actives().unwrap());
+        // Cannot be reopened while it's still active
+        assert_eq!(None, task_stat.reopen_task(done));
+    }
+
+    #[test]
+    fn set_progress_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.add_active_task("task a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        assert_eq!(0.0, task_stat.active["task a"].progress);
+
+        assert_eq!(0.5, task_stat.set_progress("task a".to_string(), 0.5).unwrap().progress);
+        assert_eq!(0.5, task_stat.active["task a"].progress);
+        assert_eq!(0.0, task_stat.set_progress("task a".to_string(), -0.3).unwrap().progress);
+        assert_eq!(1.0, task_stat.set_progress("task a".to_string(), 1.7).unwrap().progress);
+        assert_eq!(1.0, task_stat.active["task a"].progress);
+        assert_eq!(0.0, task_stat.set_progress("task a".to_string(), ::std::f32::NAN).unwrap().progress);
+        assert_eq!(None, task_stat.set_progress("task b".to_string(), 0.5));
+    }
+
+    #[test]
+    fn estimate_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        assert_eq!(0, task_stat.total_estimate_active());
+        for (title, estimate) in [("a", 30), ("b", 45), ("c", 0)].iter() {
+            task_stat.add_active_task(title.to_string(), "".to_string(), 1.0, 3).unwrap();
+            task_stat.set_estimate_minutes(title, *estimate).unwrap();
+        }
+        task_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                  1.0, 1.0, 3, 2).unwrap();
+        task_stat.set_estimate_minutes("pooled", 1000).unwrap();
+        assert_eq!(30, task_stat.active["a"].estimate_minutes());
+        assert_eq!(1000, task_stat.pool["pooled"].estimate_minutes());
+        assert_eq!(75, task_stat.total_estimate_active());
+
+        // The estimate is passed to the activated task.
+        task_stat.activate_named("pooled".to_string()).unwrap();
+        assert_eq!(1075, task_stat.total_estimate_active());
+
+        assert!(task_stat.set_estimate_minutes("unknown", 5).is_err());
+        assert!(task_stat.set_estimate_minutes("a", -5).is_err());
+        assert_eq!(1075, task_stat.total_estimate_active());
+    }
+
+    #[test]
+    fn estimate_save_load_test () {
+        remove_dir_all("savetest/task/estimate").ok();
+        let hash_io = HashIO::new("savetest/task/estimate".to_string());
+        let task = Task {
+            factor: 1.0,
+            title: "task".to_string(),
+            description: "description".to_string(),
+            estimate_minutes: 90
+        };
+        let hash = hash_io.put(&task).unwrap();
+        assert_eq!(task, hash_io.get(&hash).unwrap());
+        assert_eq!(&[0u8, 0, 0, 1], &hash_io.get_raw(&hash).unwrap()[0..4]);
+
+        // Tasks from version 0 have no estimate.
+        let mut bytes = Vec::new();
+        write_u32(0, &mut bytes).unwrap();
+        write_f32(2.0, &mut bytes).unwrap();
+        write_hash(&task.title.as_hash(), &mut bytes).unwrap();
+        write_hash(&task.description.as_hash(), &mut bytes).unwrap();
+        let old_hash = Hash::hash_bytes(&bytes);
+        hash_io.put_raw(&old_hash, &bytes).unwrap();
+        let old: Task = hash_io.get(&old_hash).unwrap();
+        assert_eq!(2.0, old.factor);
+        assert_eq!("task", old.title);
+        assert_eq!(0, old.estimate_minutes);
+        // The loaded task keeps the hash of its file, so active and pooled
+        // tasks which reference it keep their hashes too.
+        assert_eq!(old_hash, old.as_hash());
+        assert_eq!(old_hash, hash_io.put(&old).unwrap());
+        assert_eq!(bytes, hash_io.get_raw(&old_hash).unwrap());
+    }
+
+    fn fixed_tm() -> time::Tm {
+        time::at_utc(time::Timespec::new(1500000000, 0))
+    }
+
+    fn prioritized_titles(task_stat: &TaskStat) -> Vec<String> {
+        task_stat.prioritized_actives().iter()
+            .map(|a_task| a_task.task.title.clone())
+            .collect()
+    }
+
+    #[test]
+    fn prioritized_by_factor_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_active_task("low".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_stat.add_active_task("high".to_string(), "".to_string(), 3.0, 2).unwrap();
+        task_stat.add_active_task("mid".to_string(), "".to_string(), 2.0, 2).unwrap();
+        assert_eq!(vec!["high", "mid", "low"], prioritized_titles(&task_stat));
+    }
+
+    #[test]
+    fn prioritized_by_due_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_active_task("later".to_string(), "".to_string(), 1.0, 5).unwrap();
+        task_stat.add_active_task("soon".to_string(), "".to_string(), 1.0, 1).unwrap();
+        task_stat.add_active_task("today".to_string(), "".to_string(), 1.0, 0).unwrap();
+        assert_eq!(vec!["today", "soon", "later"], prioritized_titles(&task_stat));
+    }
+
+    #[test]
+    fn prioritized_by_factor_and_due_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        // urgency 3.0 / (3 + 1) = 0.75
+        task_stat.add_active_task("important".to_string(), "".to_string(), 3.0, 3).unwrap();
+        // urgency 1.0 / (0 + 1) = 1.0
+        task_stat.add_active_task("urgent".to_string(), "".to_string(), 1.0, 0).unwrap();
+        // urgency 2.0 / (7 + 1) = 0.25
+        task_stat.add_active_task("someday".to_string(), "".to_string(), 2.0, 7).unwrap();
+        // urgency 0.5 / (1 + 1) = 0.25, but due earlier than "someday"
+        task_stat.add_active_task("minor".to_string(), "".to_string(), 0.5, 1).unwrap();
+        assert_eq!(vec!["urgent", "important", "minor", "someday"],
+                   prioritized_titles(&task_stat));
+    }
+
+    #[test]
+    fn days_until_due_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_active_task("task a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        assert_eq!(Some(3), task_stat.days_until_due("task a"));
+        assert_eq!(None, task_stat.days_until_due("task b"));
+
+        task_stat.ref_tm = task_stat.ref_tm + Duration::days(5);
+        assert_eq!(Some(-2), task_stat.days_until_due("task a"));
+        let a_task = &task_stat.active["task a"];
+        assert_eq!(-2, a_task.days_until_due(&task_stat.ref_tm));
+    }
+
+    #[test]
+    fn cooldown_remaining_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_pooled_task("task a".to_string(), "".to_string(),
+                                  1.0, 1.0, 4, 2).unwrap();
+        assert_eq!(0, task_stat.pool["task a"].cooldown_remaining(&task_stat.ref_tm));
+
+        task_stat.add_active_task("task a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.mark_done("task a".to_string()).unwrap();
+        let p_task = task_stat.pool["task a"].clone();
+        assert_eq!(4, p_task.cooldown_remaining(&task_stat.ref_tm));
+        let later = task_stat.ref_tm + Duration::days(1) + Duration::hours(12);
+        assert_eq!(3, p_task.cooldown_remaining(&later));
+        let after = task_stat.ref_tm + Duration::days(5);
+        assert_eq!(0, p_task.cooldown_remaining(&after));
+    }
+
+    #[test]
+    fn report_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_active_task("past task".to_string(), "".to_string(), 1.0, 1).unwrap();
+        task_stat.add_active_task("future task".to_string(), "".to_string(), 1.0, 10).unwrap();
+        task_stat.add_pooled_task("pooled task".to_string(), "".to_string(),
+                                  1.0, 0.5, 3, 2).unwrap();
+        task_stat.ref_tm = task_stat.ref_tm + Duration::days(4);
+
+        let report = task_stat.report();
+        assert!(report.contains("past task: due 2017-07-15 (overdue by 3 days)"));
+        assert!(report.contains("future task: due 2017-07-24 (6 days left)"));
+        assert!(report.contains("pooled task: available"));
+        // Overdue tasks are listed first
+        assert!(report.find("past task").unwrap() < report.find("future task").unwrap());
+    }
+
+    #[test]
+    fn complete_task_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        let a_task = task_stat.add_active_task("task a".to_string(), "".to_string(),
+                                               1.0, 3).unwrap();
+        assert_eq!(None, task_stat.complete_task("task b".to_string()));
+        let done = task_stat.complete_task("task a".to_string()).unwrap();
+        assert_eq!(a_task.due, done.due);
+        assert_eq!(a_task, done);
+        assert_eq!(None, task_stat.complete_task("task a".to_string()));
+    }
+
+    #[test]
+    fn canonical_tm_test () {
+        let mut task_stat_1 = TaskStat::empty_task_stat();
+        task_stat_1.ref_tm = fixed_tm();
+        let mut task_stat_2 = TaskStat::empty_task_stat();
+        task_stat_2.ref_tm = fixed_tm() + Duration::hours(3);
+        task_stat_2.ref_tm.tm_wday = 0;
+        task_stat_2.ref_tm.tm_yday = 0;
+        task_stat_2.ref_tm.tm_isdst = 1;
+
+        let a_task_1 = task_stat_1.add_active_task("task".to_string(), "".to_string(),
+                                                   1.0, 3).unwrap();
+        let a_task_2 = task_stat_2.add_active_task("task".to_string(), "".to_string(),
+                                                   1.0, 3).unwrap();
+        assert_eq!(a_task_1, a_task_2);
+        assert_eq!(a_task_1.as_hash(), a_task_2.as_hash());
+        assert_eq!(fixed_tm().tm_wday, a_task_2.start.tm_wday);
+
+        let p_task_1 = task_stat_1.add_pooled_task("pooled".to_string(), "".to_string(),
+                                                   1.0, 0.5, 3, 2).unwrap();
+        let p_task_2 = task_stat_2.add_pooled_task("pooled".to_string(), "".to_string(),
+                                                   1.0, 0.5, 3, 2).unwrap();
+        assert_eq!(p_task_1.as_hash(), p_task_2.as_hash());
+    }
+
+    #[test]
+    fn update_ref_tm_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.update_ref_tm();
+        let today = time::now();
+        assert_eq!(today.tm_year, task_stat.ref_tm.tm_year);
+        assert_eq!(today.tm_yday, task_stat.ref_tm.tm_yday);
+        assert_eq!(0, task_stat.ref_tm.tm_hour);
+        assert_eq!(0, task_stat.ref_tm.tm_min);
+        assert_eq!(0, task_stat.ref_tm.tm_sec);
+    }
+
+    #[test]
+    fn activate_at_test () {
+        let mut rng = TestRand {
+            i: 0,
+            vals: vec![0],
+            vals_f: vec![0.0]
+        };
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                  1.0, 1.0, 3, 2).unwrap();
+        assert_eq!(1, task_stat.activate_at(&mut rng, fixed_tm()).unwrap().len());
+        task_stat.mark_done("pooled".to_string()).unwrap();
+
+        // Cooling down until three days after fixed_tm
+        let a_tasks = task_stat.activate_at(&mut rng, fixed_tm() + Duration::days(1)).unwrap();
+        assert!(a_tasks.is_empty());
+        let a_tasks = task_stat.activate_at(&mut rng, fixed_tm() + Duration::days(4)).unwrap();
+        assert_eq!(1, a_tasks.len());
+        assert_eq!(fixed_tm().tm_mday + 4, a_tasks[0].start.tm_mday);
+        assert_eq!(0, a_tasks[0].start.tm_hour);
+    }
+
+    #[test]
+    fn pooled_task_builder_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        let p_task = task_stat.add_pooled(PooledTaskBuilder::new("pooled".to_string())).unwrap();
+        assert_eq!("pooled", p_task.task.title);
+        assert_eq!("", p_task.task.description);
+        assert_eq!(1.0, p_task.task.factor);
+        assert_eq!(1.0, p_task.propability);
+        assert_eq!(0, p_task.cool_down);
+        assert_eq!(1, p_task.due_days);
+        assert_eq!(Some(&p_task), task_stat.pool.get("pooled"));
+
+        let p_task = task_stat.add_pooled(PooledTaskBuilder::new("other".to_string())
+                                          .with_description("desc".to_string())
+                                          .with_factor(2.0)
+                                          .with_propability(0.5)
+                                          .with_cool_down(3)
+                                          .with_due_days(4)).unwrap();
+        assert_eq!("desc", p_task.task.description);
+        assert_eq!(2.0, p_task.task.factor);
+        assert_eq!(0.5, p_task.propability);
+        assert_eq!(3, p_task.cool_down);
+        assert_eq!(4, p_task.due_days);
+    }
+
+    #[test]
+    fn add_active_task_checked_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        let a_task = task_stat.add_active_task_checked("task".to_string(), "".to_string(),
+                                                       1.0, 2).unwrap();
+        match task_stat.add_active_task_checked("task".to_string(), "other".to_string(),
+                                                1.0, 5) {
+            Err(TaskStatError::TaskExists(title)) => assert_eq!("task", title),
+            res => panic!("Expected TaskExists, got {:?}", res)
+        }
+        assert_eq!(Some(&a_task), task_stat.active.get("task"));
+    }
+
+    #[test]
+    fn save_load_test () {
+        remove_dir_all("savetest/task/save").ok();
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_active_task("task".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                  1.0, 0.5, 3, 2).unwrap();
+
+        let hash = task_stat.save("savetest/task/save").unwrap();
+        let loaded = TaskStat::load("savetest/task/save", &hash).unwrap();
+        assert_eq!(task_stat, loaded);
+        assert!(TaskStat::load("savetest/task/save", &Hash::hash_bytes(b"none")).is_err());
+    }
+
+    #[test]
+    fn contains_typed_test () {
+        remove_dir_all("savetest/task/contains_typed").ok();
+        let hash_io = HashIO::new("savetest/task/contains_typed".to_string());
+        let task = Task {
+            factor: 1.0,
+            title: "task".to_string(),
+            description: "description".to_string(),
+            estimate_minutes: 0
+        };
+        let hash = hash_io.put(&task).unwrap();
+        assert!(hash_io.contains_typed::<Task>(&hash));
+
+        let title_hash = task.title.as_hash();
+        assert!(hash_io.exists(&title_hash));
+        assert!(!hash_io.contains_typed::<Task>(&title_hash));
+        assert!(!hash_io.contains_typed::<Task>(&Hash::hash_bytes(b"none")));
+    }
+
+    #[test]
+    fn activate_named_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                  1.0, 0.0, 3, 2).unwrap();
+        assert_eq!(None, task_stat.activate_named("unknown".to_string()));
+
+        let a_task = task_stat.activate_named("pooled".to_string()).unwrap();
+        assert_eq!("pooled", a_task.task.title);
+        assert_eq!(Some(&a_task), task_stat.active.get("pooled"));
+        // Already active
+        assert_eq!(None, task_stat.activate_named("pooled".to_string()));
+
+        task_stat.mark_done("pooled".to_string()).unwrap();
+        // Cooling down
+        assert_eq!(None, task_stat.activate_named("pooled".to_string()));
+        task_stat.ref_tm = task_stat.ref_tm + Duration::days(3);
+        assert!(task_stat.activate_named("pooled".to_string()).is_some());
+    }
+
+    #[test]
+    fn date_overflow_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        let a_task = task_stat.add_active_task("normal".to_string(), "".to_string(),
+                                               1.0, 3).unwrap();
+        assert_eq!(Duration::days(3), a_task.due - task_stat.ref_tm);
+        let a_task = task_stat.add_active_task("max".to_string(), "".to_string(),
+                                               1.0, i16::max_value()).unwrap();
+        assert_eq!(Duration::days(i16::max_value() as i64), a_task.due - task_stat.ref_tm);
+        assert_eq!(207, a_task.due.tm_year);
+
+        // Close to the end of the supported range, the due date overflows.
+        let mut task_stat = TaskStat::with_ref_tm(time::at_utc(Timespec::new(MAX_DATE_SEC - 10 * 24 * 60 * 60, 0)));
+        task_stat.add_active_task("normal".to_string(), "".to_string(), 1.0, 3).unwrap();
+        let before = task_stat.clone();
+        match task_stat.add_active_task("max".to_string(), "".to_string(),
+                                        1.0, i16::max_value()) {
+            Err(TaskStatError::DateOverflow(ref title)) if title == "max" => (),
+            res => panic!("Expected DateOverflow, got {:?}", res)
+        }
+        assert_eq!(before, task_stat);
+
+        task_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                  1.0, 1.0, i16::max_value(), i16::max_value()).unwrap();
+        let before = task_stat.clone();
+        assert_eq!(None, task_stat.activate_named("pooled".to_string()));
+        assert_eq!(before, task_stat);
+        let mut rng = TestRand { i: 0, vals: vec![0], vals_f: vec![0.0] };
+        assert!(task_stat.activate(&mut rng).unwrap().is_empty());
+        assert_eq!(before, task_stat);
+
+        // The overflowing task is skipped and doesn't block other tasks.
+        task_stat.add_pooled_task("other".to_string(), "".to_string(),
+                                  1.0, 1.0, 3, 2).unwrap();
+        let mut rng = TestRand { i: 0, vals: vec![0], vals_f: vec![0.0] };
+        let a_tasks = task_stat.activate(&mut rng).unwrap();
+        assert_eq!(1, a_tasks.len());
+        assert_eq!("other", a_tasks[0].task.title);
+        assert_eq!(vec!["normal".to_string(), "other".to_string()], task_stat.active_titles());
+        task_stat.complete_task("other".to_string());
+
+        // A cool down out of range never ends instead of panicking.
+        task_stat.complete_task("normal".to_string());
+        task_stat.pool.get_mut("pooled").unwrap().due_days = 1;
+        task_stat.activate_named("pooled".to_string()).unwrap();
+        task_stat.mark_done("pooled".to_string()).unwrap();
+        assert_eq!(MAX_DATE_SEC, task_stat.pool["pooled"].cooling_until.to_timespec().sec);
+    }
+
+    fn done_with_cooldown(cooldown_override: Option<i16>) -> TaskStat {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                  1.0, 0.0, 3, 2).unwrap();
+        task_stat.activate_named("pooled".to_string()).unwrap();
+        assert!(task_stat.mark_done_with_cooldown("pooled".to_string(), cooldown_override));
+        assert!(!task_stat.mark_done_with_cooldown("pooled".to_string(), cooldown_override));
+        task_stat
+    }
+
+    #[test]
+    fn mark_done_with_cooldown_test () {
+        let task_stat = done_with_cooldown(None);
+        assert_eq!(3, task_stat.pool["pooled"].cooldown_remaining(&task_stat.ref_tm));
+
+        let mut task_stat = done_with_cooldown(Some(0));
+        assert_eq!(0, task_stat.pool["pooled"].cooldown_remaining(&task_stat.ref_tm));
+        assert!(task_stat.activate_named("pooled".to_string()).is_some());
+
+        let task_stat = done_with_cooldown(Some(7));
+        assert_eq!(7, task_stat.pool["pooled"].cooldown_remaining(&task_stat.ref_tm));
+    }
+
+    #[test]
+    fn diff_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_active_task("task a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.add_active_task("task b".to_string(), "".to_string(), 1.0, 3).unwrap();
+        assert!(task_stat.diff(&task_stat).is_empty());
+
+        let mut new_stat = task_stat.clone();
+        let done = new_stat.complete_task("task a".to_string()).unwrap();
+        let added = new_stat.add_active_task("task c".to_string(), "".to_string(),
+                                             1.0, 3).unwrap();
+        let p_task = new_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                              1.0, 0.5, 3, 2).unwrap();
+        let b_old = new_stat.active["task b"].clone();
+        let b_new = new_stat.set_progress("task b".to_string(), 0.5).unwrap();
+
+        let diff = task_stat.diff(&new_stat);
+        assert_eq!(vec![added], diff.added_actives);
+        assert_eq!(vec![done], diff.removed_actives);
+        assert_eq!(vec![(b_old, b_new)], diff.modified_actives);
+        assert_eq!(vec![p_task], diff.added_pooled);
+        assert!(diff.removed_pooled.is_empty());
+        assert!(diff.modified_pooled.is_empty());
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn hash_cache_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        task_stat.ref_tm = fixed_tm();
+        task_stat.add_active_task("task".to_string(), "".to_string(), 1.0, 3).unwrap();
+        let mut cache = HashCache::new(task_stat.clone());
+        assert!(!cache.is_cached());
+        assert_eq!(task_stat.as_hash(), cache.as_hash());
+        assert!(cache.is_cached());
+        assert_eq!(task_stat.as_hash(), cache.as_hash());
+
+        cache.get_mut().complete_task("task".to_string()).unwrap();
+        assert!(!cache.is_cached());
+        assert!(task_stat.as_hash() != cache.as_hash());
+        assert_eq!(cache.get().as_hash(), cache.as_hash());
+    }
+
+    #[test]
+    fn with_ref_tm_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm() + Duration::hours(5));
+        assert!(task_stat.active.is_empty());
+        assert!(task_stat.pool.is_empty());
+        assert_eq!(0, task_stat.ref_tm.tm_hour);
+        assert_eq!(fixed_tm().tm_mday, task_stat.ref_tm.tm_mday);
+
+        let a_task = task_stat.add_active_task("task".to_string(), "".to_string(),
+                                               1.0, 3).unwrap();
+        assert_eq!(task_stat.ref_tm, a_task.start);
+        assert_eq!(Duration::days(3), a_task.due - task_stat.ref_tm);
+    }
+
+    #[test]
+    fn activation_status_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        task_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                  1.0, 0.5, 3, 2).unwrap();
+        assert_eq!(None, task_stat.activation_status("unknown"));
+        assert_eq!(Some(ActivationStatus::Eligible),
+                   task_stat.activation_status("pooled"));
+
+        task_stat.activate_named("pooled".to_string()).unwrap();
+        assert_eq!(Some(ActivationStatus::AlreadyActive),
+                   task_stat.activation_status("pooled"));
+
+        task_stat.complete_task("pooled".to_string()).unwrap();
+        let until = task_stat.pool["pooled"].cooling_until;
+        assert_eq!(Some(ActivationStatus::CoolingDown { until: until }),
+                   task_stat.activation_status("pooled"));
+        assert_eq!(Duration::days(3), until - task_stat.ref_tm);
+    }
+
+    #[test]
+    fn hash_eq_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        task_stat.add_active_task("task".to_string(), "".to_string(), 1.0, 3).unwrap();
+        let mut other = task_stat.clone();
+        assert!(task_stat == other);
+        assert!(task_stat.hash_eq(&other));
+
+        other.set_progress("task".to_string(), 0.5).unwrap();
+        assert!(task_stat != other);
+        assert!(!task_stat.hash_eq(&other));
+    }
+
+    #[test]
+    fn actives_by_start_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        task_stat.add_active_task("b".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.ref_tm = task_stat.ref_tm + Duration::days(2);
+        task_stat.add_active_task("c".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.add_active_task("a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.ref_tm = task_stat.ref_tm + Duration::days(1);
+        task_stat.add_active_task("d".to_string(), "".to_string(), 1.0, 3).unwrap();
+
+        let titles: Vec<String> = task_stat.actives_by_start().into_iter()
+            .map(|a_task| a_task.task.title)
+            .collect();
+        assert_eq!(vec!["d", "a", "c", "b"], titles);
+    }
+
+    fn merge_stats() -> (TaskStat, TaskStat) {
+        let mut own = TaskStat::with_ref_tm(fixed_tm());
+        own.add_active_task("own".to_string(), "".to_string(), 1.0, 3).unwrap();
+        own.add_active_task("both".to_string(), "own".to_string(), 1.0, 3).unwrap();
+        own.add_pooled_task("own pooled".to_string(), "".to_string(),
+                            1.0, 0.5, 3, 2).unwrap();
+        let mut other = TaskStat::with_ref_tm(fixed_tm() + Duration::days(1));
+        other.add_active_task("other".to_string(), "".to_string(), 1.0, 3).unwrap();
+        other.add_active_task("both".to_string(), "other".to_string(), 1.0, 3).unwrap();
+        other.add_pooled_task("other pooled".to_string(), "".to_string(),
+                              1.0, 0.5, 3, 2).unwrap();
+        (own, other)
+    }
+
+    #[test]
+    fn merge_test () {
+        let (mut own, other) = merge_stats();
+        own.active.remove("both");
+        let mut expected = own.clone();
+        own.merge(&other, MergeStrategy::KeepSelf);
+        expected.active.insert("other".to_string(), other.active["other"].clone());
+        expected.active.insert("both".to_string(), other.active["both"].clone());
+        expected.pool.insert("other pooled".to_string(), other.pool["other pooled"].clone());
+        assert_eq!(expected, own);
+        assert_eq!(vec!["other pooled", "own pooled"], own.pooled_titles());
+    }
+
+    #[test]
+    fn merge_collision_test () {
+        let description = |strategy: MergeStrategy, reverse: bool| {
+            let (mut own, mut other) = merge_stats();
+            if reverse {
+                ::std::mem::swap(&mut own, &mut other);
+            }
+            own.merge(&other, strategy);
+            assert_eq!(3, own.active.len());
+            assert_eq!(2, own.pool.len());
+            own.active["both"].task.description.clone()
+        };
+        assert_eq!("own", description(MergeStrategy::KeepSelf, false));
+        assert_eq!("other", description(MergeStrategy::KeepOther, false));
+        assert_eq!("other", description(MergeStrategy::KeepNewest, false));
+        assert_eq!("other", description(MergeStrategy::KeepNewest, true));
+        assert_eq!("own", description(MergeStrategy::KeepOther, true));
+    }
+
+    #[test]
+    fn merge_pooled_collision_test () {
+        let mut own = TaskStat::with_ref_tm(fixed_tm());
+        own.add_pooled_task("pooled".to_string(), "own".to_string(), 1.0, 0.5, 3, 2).unwrap();
+        let mut other = own.clone();
+        other.pool.get_mut("pooled").unwrap().task.description = "other".to_string();
+        other.pool.get_mut("pooled").unwrap().cooling_until = fixed_tm() + Duration::days(2);
+
+        let mut merged = own.clone();
+        merged.merge(&other, MergeStrategy::KeepSelf);
+        assert_eq!("own", merged.pool["pooled"].task.description);
+        merged.merge(&other, MergeStrategy::KeepNewest);
+        assert_eq!("other", merged.pool["pooled"].task.description);
+        own.merge(&other, MergeStrategy::KeepOther);
+        assert_eq!("other", own.pool["pooled"].task.description);
+    }
+
+    #[test]
+    fn add_pooled_task_validation_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        let nan = ::std::f32::NAN;
+        match task_stat.add_pooled_task("nan".to_string(), "".to_string(), 1.0, nan, 3, 2) {
+            Err(TaskStatError::InvalidValue(_)) => (),
+            res => panic!("Expected InvalidValue, got {:?}", res)
+        }
+        match task_stat.add_pooled_task("negative".to_string(), "".to_string(),
+                                        1.0, -0.1, 3, 2) {
+            Err(TaskStatError::InvalidValue(_)) => (),
+            res => panic!("Expected InvalidValue, got {:?}", res)
+        }
+        match task_stat.add_pooled_task("factor".to_string(), "".to_string(), nan, 0.5, 3, 2) {
+            Err(TaskStatError::InvalidValue(_)) => (),
+            res => panic!("Expected InvalidValue, got {:?}", res)
+        }
+        assert!(task_stat.pool.is_empty());
+
+        let p_task = task_stat.add_pooled_task("clamped".to_string(), "".to_string(),
+                                               2.0, 1.5, 3, 2).unwrap();
+        assert_eq!(1.0, p_task.propability);
+        assert_eq!(2.0, p_task.task.factor);
+        assert_eq!(1.0, task_stat.pool["clamped"].propability);
+    }
+
+    #[test]
+    fn purge_pooled_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        let zero_b = task_stat.add_pooled_task("zero b".to_string(), "".to_string(),
+                                               1.0, 0.0, 3, 2).unwrap();
+        let zero_a = task_stat.add_pooled_task("zero a".to_string(), "".to_string(),
+                                               1.0, 0.0, 3, 2).unwrap();
+        task_stat.add_pooled_task("picked".to_string(), "".to_string(),
+                                  1.0, 0.5, 3, 2).unwrap();
+
+        assert_eq!(vec![zero_a, zero_b], task_stat.purge_never_picked());
+        assert_eq!(vec!["picked".to_string()], task_stat.pooled_titles());
+        assert!(task_stat.purge_never_picked().is_empty());
+        assert_eq!(1, task_stat.purge_pooled(|p_task| p_task.cool_down == 3).len());
+        assert!(task_stat.pool.is_empty());
+    }
+
+    #[test]
+    fn max_active_test () {
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        assert_eq!(None, task_stat.max_active);
+        task_stat.set_max_active(Some(2));
+        task_stat.add_active_task("a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        assert!(!task_stat.is_active_limit_reached());
+        task_stat.add_active_task("b".to_string(), "".to_string(), 1.0, 3).unwrap();
+        assert!(task_stat.is_active_limit_reached());
+        match task_stat.add_active_task("c".to_string(), "".to_string(), 1.0, 3) {
+            Err(TaskStatError::ActiveLimitReached(2)) => (),
+            res => panic!("Expected ActiveLimitReached, got {:?}", res)
+        }
+        // Replacing an active task is still possible.
+        task_stat.add_active_task("b".to_string(), "".to_string(), 1.0, 5).unwrap();
+
+        task_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                  1.0, 1.0, 3, 2).unwrap();
+        assert_eq!(None, task_stat.activate_named("pooled".to_string()));
+        let mut rng = TestRand { i: 0, vals: vec![0], vals_f: vec![0.0] };
+        assert!(task_stat.activate(&mut rng).unwrap().is_empty());
+
+        task_stat.complete_task("a".to_string()).unwrap();
+        assert!(task_stat.activate(&mut rng).unwrap().len() == 1);
+        assert_eq!(2, task_stat.active.len());
+
+        // A lowered limit is reported, not the number of active tasks.
+        task_stat.set_max_active(Some(1));
+        match task_stat.add_active_task("c".to_string(), "".to_string(), 1.0, 3) {
+            Err(TaskStatError::ActiveLimitReached(1)) => (),
+            res => panic!("Expected ActiveLimitReached(1), got {:?}", res)
+        }
+
+        task_stat.set_max_active(None);
+        task_stat.add_active_task("c".to_string(), "".to_string(), 1.0, 3).unwrap();
+        assert_eq!(3, task_stat.active.len());
+    }
+
+    #[test]
+    fn max_active_save_load_test () {
+        remove_dir_all("savetest/task/max_active").ok();
+        let mut task_stat = TaskStat::with_ref_tm(fixed_tm());
+        let hash_io = HashIO::new("savetest/task/max_active".to_string());
+
+        // Without a limit, the state is stored like before the limit existed
+        // and keeps its hash.
+        let hash = task_stat.save("savetest/task/max_active").unwrap();
+        assert_eq!(&[0u8, 0, 0, 0], &hash_io.get_raw(&hash).unwrap()[0..4]);
+        let loaded = TaskStat::load("savetest/task/max_active", &hash).unwrap();
+        assert_eq!(hash, loaded.as_hash());
+
+        task_stat.set_max_active(Some(3));
+        let hash = task_stat.save("savetest/task/max_active").unwrap();
+        assert_eq!(&[0u8, 0, 0, 1], &hash_io.get_raw(&hash).unwrap()[0..4]);
+        assert_eq!(task_stat, TaskStat::load("savetest/task/max_active", &hash).unwrap());
+    }
+
+    #[test]
+    fn titles_test () {
+        let mut task_stat = TaskStat::empty_task_stat();
+        assert!(task_stat.active_titles().is_empty());
+        assert!(task_stat.pooled_titles().is_empty());
+        task_stat.add_active_task("b".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.add_active_task("a".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.add_pooled_task("p".to_string(), "".to_string(), 1.0, 0.5, 3, 2).unwrap();
+        assert_eq!(vec!["a".to_string(), "b".to_string()], task_stat.active_titles());
+        assert_eq!(vec!["p".to_string()], task_stat.pooled_titles());
+        let keys: Vec<String> = task_stat.active.keys().cloned().collect();
+        assert_eq!(keys, task_stat.active_titles());
+    }
 }
@@ -16,6 +16,9 @@ use std::io;
 use self::time::{Tm, now};
 use std::fmt;
 use std::error;
+use std::mem::replace;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
 #[derive(Debug)]
 pub enum TaskLogError {
@@ -69,11 +72,63 @@ impl From<io::Error> for TaskLogError {
 
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum TaskAction {
     ScheduleTask(ActiveTask),
     PoolTask(PooledTask),
     CompleteTask(ActiveTask),
-    ActivateTask(Vec<ActiveTask>)
+    ActivateTask(Vec<ActiveTask>),
+    ReopenTask(ActiveTask),
+    CompleteMany(Vec<ActiveTask>),
+    Batch(Vec<TaskAction>),
+    RenameTask { old: String, new: String },
+    RemovePooledTasks(Vec<PooledTask>),
+    RemoveActiveTasks(Vec<ActiveTask>)
+}
+
+impl TaskAction {
+    /// Describe the changes of a TaskStatDiff as actions.
+    ///
+    /// Added or modified active tasks become ScheduleTask, added or
+    /// modified pooled tasks PoolTask and removed tasks RemoveActiveTasks
+    /// and RemovePooledTasks.  A diff can't tell if an active task was
+    /// completed, renamed or deleted, so removals are never completions.
+    pub fn from_diff(diff: &TaskStatDiff) -> Vec<TaskAction> {
+        let mut res = Vec::new();
+        if !diff.removed_actives.is_empty() {
+            res.push(TaskAction::RemoveActiveTasks(diff.removed_actives.clone()));
+        }
+        for &(_, ref a_task) in diff.modified_actives.iter() {
+            res.push(TaskAction::ScheduleTask(a_task.clone()));
+        }
+        for a_task in diff.added_actives.iter() {
+            res.push(TaskAction::ScheduleTask(a_task.clone()));
+        }
+        for &(_, ref p_task) in diff.modified_pooled.iter() {
+            res.push(TaskAction::PoolTask(p_task.clone()));
+        }
+        for p_task in diff.added_pooled.iter() {
+            res.push(TaskAction::PoolTask(p_task.clone()));
+        }
+        if !diff.removed_pooled.is_empty() {
+            res.push(TaskAction::RemovePooledTasks(diff.removed_pooled.clone()));
+        }
+        res
+    }
+}
+
+/// Add the tasks completed by action to res.
+fn collect_completions(action: &TaskAction, res: &mut Vec<ActiveTask>) {
+    match action {
+        &TaskAction::CompleteTask(ref a_task) => res.push(a_task.clone()),
+        &TaskAction::CompleteMany(ref a_tasks) => res.extend(a_tasks.iter().cloned()),
+        &TaskAction::Batch(ref actions) => {
+            for action in actions.iter() {
+                collect_completions(action, res);
+            }
+        },
+        _ => ()
+    }
 }
 
 impl Writable for TaskAction {
@@ -97,6 +152,30 @@ impl Writable for TaskAction {
             &TaskAction::ActivateTask(ref a_tasks) => {
                 size += try!(write_u8(4, write));
                 size += try!(write_hash(&a_tasks.as_hash(), write));
+            },
+            &TaskAction::ReopenTask(ref a_task) => {
+                size += try!(write_u8(5, write));
+                size += try!(write_hash(&a_task.as_hash(), write));
+            },
+            &TaskAction::CompleteMany(ref a_tasks) => {
+                size += try!(write_u8(6, write));
+                size += try!(write_hash(&a_tasks.as_hash(), write));
+            },
+            &TaskAction::Batch(ref actions) => {
+                size += try!(write_u8(7, write));
+                size += try!(write_hash(&actions.as_hash(), write));
+            },
+            &TaskAction::RenameTask { ref old, ref new } => {
+                size += try!(write_u8(8, write));
+                size += try!(write_hash(&(old.clone(), new.clone()).as_hash(), write));
+            },
+            &TaskAction::RemovePooledTasks(ref p_tasks) => {
+                size += try!(write_u8(9, write));
+                size += try!(write_hash(&p_tasks.as_hash(), write));
+            },
+            &TaskAction::RemoveActiveTasks(ref a_tasks) => {
+                size += try!(write_u8(10, write));
+                size += try!(write_hash(&a_tasks.as_hash(), write));
             }
         };
         Ok(size)
@@ -106,18 +185,45 @@ impl Writable for TaskAction {
 hashable_for_writable!(TaskAction);
 
 impl HashIOImpl<TaskAction> for HashIO {
+    fn store_childs(&self, hashable: &TaskAction) -> Result<(), HashIOError> {
+        try!(match hashable {
+            &TaskAction::ScheduleTask(ref a_task) => self.put(a_task),
+            &TaskAction::PoolTask(ref p_task) => self.put(p_task),
+            &TaskAction::CompleteTask(ref a_task) => self.put(a_task),
+            &TaskAction::ActivateTask(ref a_tasks) => self.put(a_tasks),
+            &TaskAction::ReopenTask(ref a_task) => self.put(a_task),
+            &TaskAction::CompleteMany(ref a_tasks) => self.put(a_tasks),
+            &TaskAction::Batch(ref actions) => self.put(actions),
+            &TaskAction::RenameTask { ref old, ref new } => self.put(&(old.clone(), new.clone())),
+            &TaskAction::RemovePooledTasks(ref p_tasks) => self.put(p_tasks),
+            &TaskAction::RemoveActiveTasks(ref a_tasks) => self.put(a_tasks)
+        });
+        Ok(())
+    }
+
     fn store_hashable<W>(&self, hashable: &TaskAction, write: &mut W) -> Result<(), HashIOError>
                     where W: Write {
-        match hashable {
-            &TaskAction::ScheduleTask(ref a_task) => try!(self.put(a_task)),
-            &TaskAction::PoolTask(ref p_task) => try!(self.put(p_task)),
-            &TaskAction::CompleteTask(ref a_task) => try!(self.put(a_task)),
-            &TaskAction::ActivateTask(ref a_tasks) => try!(self.put(a_tasks))
-        }
         try!(hashable.write_to(write));
         Ok(())
     }
 
+    fn walk_childs<F>(&self, hashable: &TaskAction, visit: &mut F) -> Result<(), HashIOError>
+                    where F: FnMut(&Hash, &EntrySize) -> Result<bool, HashIOError> {
+        match hashable {
+            &TaskAction::ScheduleTask(ref a_task) => self.walk(a_task, visit),
+            &TaskAction::PoolTask(ref p_task) => self.walk(p_task, visit),
+            &TaskAction::CompleteTask(ref a_task) => self.walk(a_task, visit),
+            &TaskAction::ActivateTask(ref a_tasks) => self.walk(a_tasks, visit),
+            &TaskAction::ReopenTask(ref a_task) => self.walk(a_task, visit),
+            &TaskAction::CompleteMany(ref a_tasks) => self.walk(a_tasks, visit),
+            &TaskAction::Batch(ref actions) => self.walk(actions, visit),
+            &TaskAction::RenameTask { ref old, ref new } =>
+                self.walk(&(old.clone(), new.clone()), visit),
+            &TaskAction::RemovePooledTasks(ref p_tasks) => self.walk(p_tasks, visit),
+            &TaskAction::RemoveActiveTasks(ref a_tasks) => self.walk(a_tasks, visit)
+        }
+    }
+
     fn receive_hashable<R>(&self, read: &mut R) -> Result<TaskAction, HashIOError>
                     where R: Read {
         let _  = try!(read_u32(read)); // version
@@ -140,6 +246,30 @@ impl HashIOImpl<TaskAction> for HashIO {
                 let a_tasks: Vec<ActiveTask> = try!(self.get(&hash));
                 TaskAction::ActivateTask(a_tasks)
             }
+            5 => {
+                let a_task: ActiveTask = try!(self.get(&hash));
+                TaskAction::ReopenTask(a_task)
+            }
+            6 => {
+                let a_tasks: Vec<ActiveTask> = try!(self.get(&hash));
+                TaskAction::CompleteMany(a_tasks)
+            }
+            7 => {
+                let actions: Vec<TaskAction> = try!(self.get(&hash));
+                TaskAction::Batch(actions)
+            }
+            8 => {
+                let (old, new): (String, String) = try!(self.get(&hash));
+                TaskAction::RenameTask { old: old, new: new }
+            }
+            9 => {
+                let p_tasks: Vec<PooledTask> = try!(self.get(&hash));
+                TaskAction::RemovePooledTasks(p_tasks)
+            }
+            10 => {
+                let a_tasks: Vec<ActiveTask> = try!(self.get(&hash));
+                TaskAction::RemoveActiveTasks(a_tasks)
+            }
             _ => {
                 return Err(HashIOError::Undefined(format!("Task Action id undefined: {}",
                                                           action_type)));
@@ -162,6 +292,15 @@ pub struct TaskLog {
     pub state: TaskStat
 }
 
+/// Describes what TaskLog::open found at the given path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadOutcome {
+    /// No log existed, the TaskLog starts with an empty state.
+    Fresh,
+    /// The state of the existing log head was loaded.
+    LoadedCurrent
+}
+
 impl TaskLog {
     pub fn new(path: String) -> TaskLog {
         TaskLog {
@@ -170,6 +309,22 @@ impl TaskLog {
         }
     }
 
+    /// Open the TaskLog at the path and load the state of its head.
+    ///
+    /// # Errors
+    /// Fails if a head is stored but it or its state could not be loaded.
+    pub fn open(path: String) -> Result<(TaskLog, LoadOutcome), TaskLogError> {
+        let mut task_log = TaskLog {
+            log: try!(IOLog::open(path)),
+            state: TaskStat::empty_task_stat()
+        };
+        if task_log.log.head_hash().is_none() {
+            return Ok((task_log, LoadOutcome::Fresh));
+        }
+        try!(task_log.load_head());
+        Ok((task_log, LoadOutcome::LoadedCurrent))
+    }
+
     pub fn load_head(&mut self) -> Result<(), TaskLogError> {
         let stat_hash = self.log.head_hash();
         match stat_hash {
@@ -182,16 +337,191 @@ impl TaskLog {
         Ok(())
     }
 
-    pub fn store_state(&mut self, action: TaskAction) -> Result<(), TaskLogError> {
+    /// Log the action together with the current state.
+    ///
+    /// Returns the hash of the new log entry.
+    pub fn store_state(&mut self, action: TaskAction) -> Result<Hash, TaskLogError> {
         let tm = now();
         let entry = TaskLogEntry {
             timestamp: tm,
             action: action,
             state: self.state.clone()
         };
-        self.log.push(entry);
+        let hash = self.log.push(entry);
+        if hash.is_none() {
+            return Err(TaskLogError::LogError(
+                LogError::CustomError("Could not store the log entry".to_string())));
+        }
+        Ok(hash)
+    }
+
+    /// Apply several changes to the state and log them as one entry.
+    ///
+    /// f works on a copy of the state.  If it succeeds, the copy becomes the
+    /// new state and one entry with a Batch action describing the changes is
+    /// logged.  If f fails or nothing changed, neither the state nor the log
+    /// are modified.
+    ///
+    /// Active tasks which f removes are logged as RemoveActiveTasks, even if
+    /// they were completed, so they don't count as completions.  Use
+    /// mark_done outside of a transaction to log a completion.
+    pub fn transaction<F>(&mut self, f: F) -> Result<(), TaskLogError>
+                where F: FnOnce(&mut TaskStat) -> Result<(), TaskLogError> {
+        let mut state = self.state.clone();
+        state.update_ref_tm();
+        try!(f(&mut state));
+        let actions = TaskAction::from_diff(&self.state.diff(&state));
+        if actions.is_empty() {
+            return Ok(());
+        }
+        let old_state = replace(&mut self.state, state);
+        if let Err(err) = self.store_state(TaskAction::Batch(actions)) {
+            self.state = old_state;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Check that the hashes of all log entries match their content.
+    ///
+    /// Detects if the task history was modified on disk.  Every entry is
+    /// read again from disk, so this needs O(n) IO for n entries.
+    pub fn verify(&self) -> Option<LogVerifyFailure<TaskLogEntry>> {
+        verify_log(&self.log)
+    }
+
+    /// Hash of the most recent log entry.
+    pub fn last_hash(&self) -> Option<Hash> {
+        self.log.head_hash()
+    }
+
+    /// Number of entries in the log.
+    pub fn history_len(&self) -> usize {
+        LogIteratorHash::from_log(&self.log).count()
+    }
+
+    /// Timestamp of the most recent log entry.
+    ///
+    /// Returns None if the log is empty or its head could not be loaded.
+    pub fn head_timestamp(&self) -> Option<Tm> {
+        self.log.head_hash()
+            .and_then(|hash| self.log.get(hash).ok())
+            .map(|entry| entry.timestamp)
+    }
+
+    /// All actions logged between from and to, both inclusive.
+    ///
+    /// The result is ordered from the oldest to the newest action.  The log
+    /// is walked from the head and reading stops at the first entry before
+    /// from.
+    pub fn actions_between(&self, from: Tm, to: Tm) -> Vec<(Tm, TaskAction)> {
+        let mut res = Vec::new();
+        for entry in LogIteratorRef::from_log(&self.log) {
+            if entry.timestamp < from {
+                break;
+            }
+            if entry.timestamp <= to {
+                res.push((entry.timestamp, entry.action));
+            }
+        }
+        res.reverse();
+        res
+    }
+
+    /// The last n completed tasks with the time they were completed.
+    ///
+    /// The newest completion comes first.  Tasks completed by CompleteMany
+    /// or inside a Batch are included.
+    pub fn recent_completions(&self, n: usize) -> Vec<(Tm, ActiveTask)> {
+        let mut res = Vec::new();
+        for entry in LogIteratorRef::from_log(&self.log) {
+            if res.len() >= n {
+                break;
+            }
+            let mut completed = Vec::new();
+            collect_completions(&entry.action, &mut completed);
+            for a_task in completed.into_iter().rev() {
+                res.push((entry.timestamp, a_task));
+            }
+        }
+        res.truncate(n);
+        res
+    }
+
+    /// Show which tasks activate would activate without doing it.
+    ///
+    /// The activation runs on a copy of the state, so neither the state
+    /// nor the log are modified.  Using a rng in the same state as the one
+    /// passed to activate later gives the same result.
+    pub fn preview_activation<R: rand::Rng>(&self, rng: &mut R) -> Result<Vec<ActiveTask>, TaskLogError> {
+        let mut state = self.state.clone();
+        state.update_ref_tm();
+        Ok(try!(state.activate(rng)))
+    }
+
+    /// Activate the pooled task with the given title and log it.
+    ///
+    /// Returns None and logs nothing if the task could not be activated.
+    pub fn activate_named(&mut self, title: String) -> Result<Option<ActiveTask>, TaskLogError> {
+        self.state.update_ref_tm();
+        let activated = self.state.activate_named(title);
+        if let Some(ref a_task) = activated {
+            try!(self.store_state(TaskAction::ActivateTask(vec![a_task.clone()])));
+        }
+        Ok(activated)
+    }
+
+    /// Put a completed task back to the active tasks and log it.
+    ///
+    /// Returns None and logs nothing if a task with the same title is
+    /// already active.
+    pub fn reopen_task(&mut self, a_task: ActiveTask) -> Result<Option<ActiveTask>, TaskLogError> {
+        self.state.update_ref_tm();
+        let reopened = self.state.reopen_task(a_task);
+        if let Some(ref a_task) = reopened {
+            try!(self.store_state(TaskAction::ReopenTask(a_task.clone())));
+        }
+        Ok(reopened)
+    }
+
+    /// Rename a task and log it.
+    pub fn rename_task(&mut self, old: String, new: String) -> Result<(), TaskLogError> {
+        self.state.update_ref_tm();
+        try!(self.state.rename_task(old.clone(), new.clone()));
+        try!(self.store_state(TaskAction::RenameTask { old: old, new: new }));
         Ok(())
     }
+
+    /// Remove all pooled tasks for which predicate returns true and log it.
+    ///
+    /// Returns the removed tasks.  Nothing is logged if no task matched.
+    pub fn purge_pooled<F>(&mut self, predicate: F) -> Result<Vec<PooledTask>, TaskLogError>
+                where F: Fn(&PooledTask) -> bool {
+        self.state.update_ref_tm();
+        let p_tasks = self.state.purge_pooled(predicate);
+        if !p_tasks.is_empty() {
+            try!(self.store_state(TaskAction::RemovePooledTasks(p_tasks.clone())));
+        }
+        Ok(p_tasks)
+    }
+
+    /// Remove all pooled tasks which are never activated randomly and log it.
+    pub fn purge_never_picked(&mut self) -> Result<Vec<PooledTask>, TaskLogError> {
+        self.purge_pooled(|p_task| p_task.propability == 0.0)
+    }
+
+    /// Complete all active tasks with the given titles in one log entry.
+    ///
+    /// Returns the tasks which were active and got removed.  Nothing is
+    /// logged if none of the titles was active.
+    pub fn mark_done_many(&mut self, titles: &[String]) -> Result<Vec<ActiveTask>, TaskLogError> {
+        self.state.update_ref_tm();
+        let a_tasks = self.state.mark_done_many(titles);
+        if !a_tasks.is_empty() {
+            try!(self.store_state(TaskAction::CompleteMany(a_tasks.clone())));
+        }
+        Ok(a_tasks)
+    }
 }
 
 
@@ -241,4 +571,412 @@ impl TaskStatTrait for TaskLog {
         let p_tasks = try!(self.state.all_pooled());
         Ok(p_tasks)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::{copy, create_dir_all, read_dir, remove_dir_all, remove_file, File};
+    use std::path::Path;
+
+    fn copy_dir(from: &Path, to: &Path) {
+        create_dir_all(to).unwrap();
+        for dir_entry in read_dir(from).unwrap() {
+            let path = dir_entry.unwrap().path();
+            let target = to.join(path.file_name().unwrap());
+            if path.is_dir() {
+                copy_dir(&path, &target);
+            } else {
+                copy(&path, &target).unwrap();
+            }
+        }
+    }
+
+    /// testdata/tasklog_v0 was written before the models got versioned
+    /// attributes.  It has a pooled task, an active task and a completed one.
+    #[test]
+    fn open_version_0_test() {
+        remove_dir_all("savetest/tasklog/v0").ok();
+        copy_dir(Path::new("testdata/tasklog_v0"), Path::new("savetest/tasklog/v0"));
+        let head = read_hash(&mut File::open("savetest/tasklog/v0/head").unwrap()).unwrap();
+
+        let mut task_log = TaskLog::new("savetest/tasklog/v0".to_string());
+        task_log.load_head().unwrap();
+        assert_eq!(Some(head), task_log.log.head_hash());
+        assert_eq!(4, LogIteratorHash::from_log(&task_log.log).count());
+        assert_eq!(vec!["active"], task_log.state.active.keys().collect::<Vec<_>>());
+        assert_eq!(0.0, task_log.state.active["active"].progress);
+        assert_eq!(vec!["pooled"], task_log.state.pool.keys().collect::<Vec<_>>());
+
+        // New entries are chained to the old ones.
+        task_log.add_active_task("new".to_string(), "".to_string(), 1.0, 2).unwrap();
+        assert_eq!(5, LogIteratorHash::from_log(&task_log.log).count());
+        let mut task_log_2 = TaskLog::new("savetest/tasklog/v0".to_string());
+        task_log_2.load_head().unwrap();
+        assert_eq!(5, LogIteratorHash::from_log(&task_log_2.log).count());
+        assert_eq!(task_log.state, task_log_2.state);
+    }
+
+    fn add_and_complete<S: TaskStatTrait>(task_stat: &mut S) -> Vec<ActiveTask> {
+        task_stat.add_active_task("task a".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_stat.add_active_task("task b".to_string(), "".to_string(), 1.0, 3).unwrap();
+        task_stat.mark_done("task a".to_string()).unwrap();
+        assert!(task_stat.mark_done("task c".to_string()).is_err());
+        task_stat.all_actives().unwrap()
+    }
+
+    #[test]
+    fn generic_trait_test() {
+        remove_dir_all("savetest/tasklog/generic").ok();
+        let mut task_stat = TaskStat::empty_task_stat();
+        let mut task_log = TaskLog::new("savetest/tasklog/generic".to_string());
+
+        let stat_actives = add_and_complete(&mut task_stat);
+        let log_actives = add_and_complete(&mut task_log);
+        assert_eq!(1, stat_actives.len());
+        assert_eq!(1, log_actives.len());
+        assert_eq!(stat_actives[0].task, log_actives[0].task);
+    }
+
+    #[test]
+    fn open_test() {
+        remove_dir_all("savetest/tasklog/open").ok();
+        let (mut task_log, outcome) = TaskLog::open("savetest/tasklog/open".to_string()).unwrap();
+        assert_eq!(LoadOutcome::Fresh, outcome);
+        task_log.add_active_task("task a".to_string(), "".to_string(), 1.0, 2).unwrap();
+
+        let (task_log_2, outcome) = TaskLog::open("savetest/tasklog/open".to_string()).unwrap();
+        assert_eq!(LoadOutcome::LoadedCurrent, outcome);
+        assert_eq!(task_log.state, task_log_2.state);
+
+        // An empty head file is an error and not a fresh log.
+        File::create("savetest/tasklog/open/head").unwrap();
+        match TaskLog::open("savetest/tasklog/open".to_string()) {
+            Err(TaskLogError::LogError(_)) => (),
+            Err(err) => panic!("Expected LogError, got {:?}", err),
+            Ok((_, outcome)) => panic!("Expected LogError, got {:?}", outcome)
+        }
+    }
+
+    #[test]
+    fn history_len_test() {
+        remove_dir_all("savetest/tasklog/history").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/history".to_string());
+        assert_eq!(0, task_log.history_len());
+        assert_eq!(None, task_log.head_timestamp());
+
+        task_log.add_active_task("task a".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_log.add_active_task("task b".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_log.mark_done("task a".to_string()).unwrap();
+        assert_eq!(3, task_log.history_len());
+        let head_entry = task_log.log.get(task_log.log.head_hash().unwrap()).unwrap();
+        assert_eq!(Some(head_entry.timestamp), task_log.head_timestamp());
+    }
+
+    #[test]
+    fn actions_between_test() {
+        remove_dir_all("savetest/tasklog/between").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/between".to_string());
+        let start = time::at_utc(time::Timespec::new(1500000000, 0));
+        let mut actions = Vec::new();
+        for i in 0..5 {
+            let a_task = task_log.state.add_active_task(format!("task {}", i), "".to_string(),
+                                                        1.0, 2).unwrap();
+            let action = TaskAction::ScheduleTask(a_task);
+            let timestamp = start + time::Duration::days(i);
+            task_log.log.push(TaskLogEntry {
+                timestamp: timestamp,
+                action: action.clone(),
+                state: task_log.state.clone()
+            });
+            actions.push((timestamp, action));
+        }
+
+        let between = task_log.actions_between(start + time::Duration::days(1),
+                                               start + time::Duration::days(3));
+        assert_eq!(actions[1..4].to_vec(), between);
+        assert!(task_log.actions_between(start + time::Duration::days(5),
+                                         start + time::Duration::days(6)).is_empty());
+    }
+
+    #[test]
+    fn verify_test() {
+        remove_dir_all("savetest/tasklog/verify").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/verify".to_string());
+        assert!(task_log.verify().is_none());
+        task_log.add_active_task("task a".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_log.add_active_task("task b".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_log.add_active_task("task c".to_string(), "".to_string(), 1.0, 2).unwrap();
+        assert!(task_log.verify().is_none());
+
+        // Replace the stored entry of the second newest log item.
+        let hashes: Vec<Hash> = LogIteratorHash::from_log(&task_log.log).collect();
+        let mut entry = task_log.log.get(hashes[1]).unwrap();
+        let entry_hash = entry.as_hash();
+        entry.timestamp = entry.timestamp + time::Duration::days(1);
+        let forged_hash = task_log.log.hashio.put(&entry).unwrap();
+        let forged = task_log.log.hashio.get_raw(&forged_hash).unwrap();
+        remove_file(task_log.log.hashio.filename_for_hash(&entry_hash)).unwrap();
+        task_log.log.hashio.put_raw(&entry_hash, &forged).unwrap();
+
+        match task_log.verify() {
+            Some(LogVerifyFailure::LogHashFailure { t, actual_hash, depth, .. }) => {
+                assert_eq!(entry, t);
+                assert_eq!(hashes[1], actual_hash);
+                assert_eq!(1, depth);
+            },
+            res => panic!("Expected LogHashFailure, got {:?}", res)
+        }
+    }
+
+    #[test]
+    fn recent_completions_test() {
+        remove_dir_all("savetest/tasklog/recent_completions").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/recent_completions".to_string());
+        assert!(task_log.recent_completions(3).is_empty());
+        for title in ["a", "b", "c", "d", "e"].iter() {
+            task_log.add_active_task(title.to_string(), "".to_string(), 1.0, 2).unwrap();
+        }
+        task_log.mark_done("b".to_string()).unwrap();
+        task_log.mark_done("a".to_string()).unwrap();
+        task_log.mark_done_many(&["c".to_string(), "d".to_string()]).unwrap();
+
+        let titles = |completions: Vec<(Tm, ActiveTask)>| -> Vec<String> {
+            completions.into_iter().map(|(_, a_task)| a_task.task.title).collect()
+        };
+        assert_eq!(vec!["d", "c", "a"], titles(task_log.recent_completions(3)));
+        assert_eq!(vec!["d", "c", "a", "b"], titles(task_log.recent_completions(10)));
+        assert!(task_log.recent_completions(0).is_empty());
+    }
+
+    #[test]
+    fn purge_never_picked_test() {
+        remove_dir_all("savetest/tasklog/purge").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/purge".to_string());
+        let p_task = task_log.add_pooled_task("never".to_string(), "".to_string(),
+                                              1.0, 0.0, 3, 2).unwrap();
+        task_log.add_pooled_task("sometimes".to_string(), "".to_string(),
+                                 1.0, 0.5, 3, 2).unwrap();
+        assert_eq!(vec![p_task.clone()], task_log.purge_never_picked().unwrap());
+        assert_eq!(1, task_log.all_pooled().unwrap().len());
+
+        let task_log = TaskLog::new("savetest/tasklog/purge".to_string());
+        let entry = task_log.log.get(task_log.log.head_hash().unwrap()).unwrap();
+        assert_eq!(TaskAction::RemovePooledTasks(vec![p_task]), entry.action);
+        let history_len = task_log.history_len();
+
+        // Nothing to purge, nothing is logged.
+        let mut task_log = task_log;
+        assert!(task_log.purge_never_picked().unwrap().is_empty());
+        assert_eq!(history_len, task_log.history_len());
+    }
+
+    #[test]
+    fn preview_activation_test() {
+        use super::rand::{SeedableRng, StdRng};
+
+        remove_dir_all("savetest/tasklog/preview").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/preview".to_string());
+        task_log.add_pooled_task("always".to_string(), "".to_string(), 1.0, 1.0, 3, 2).unwrap();
+        task_log.add_pooled_task("never".to_string(), "".to_string(), 1.0, 0.0, 3, 2).unwrap();
+        let head = task_log.last_hash();
+
+        let seed: &[_] = &[1, 2, 3];
+        let preview = task_log.preview_activation(&mut StdRng::from_seed(seed)).unwrap();
+        assert_eq!(vec!["always".to_string()],
+                   preview.iter().map(|a_task| a_task.task.title.clone()).collect::<Vec<_>>());
+        assert!(task_log.all_actives().unwrap().is_empty());
+        assert_eq!(head, task_log.last_hash());
+
+        let activated = task_log.activate(&mut StdRng::from_seed(seed)).unwrap();
+        assert_eq!(preview, activated);
+    }
+
+    #[test]
+    fn rename_task_test() {
+        remove_dir_all("savetest/tasklog/rename").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/rename".to_string());
+        task_log.add_active_task("old".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_log.rename_task("old".to_string(), "new".to_string()).unwrap();
+        assert_eq!("new", task_log.all_actives().unwrap()[0].task.title);
+        assert!(task_log.rename_task("old".to_string(), "other".to_string()).is_err());
+
+        // The action is logged and can be read again.
+        let task_log = TaskLog::new("savetest/tasklog/rename".to_string());
+        let entry = task_log.log.get(task_log.log.head_hash().unwrap()).unwrap();
+        assert_eq!(TaskAction::RenameTask { old: "old".to_string(), new: "new".to_string() },
+                   entry.action);
+        assert_eq!(2, task_log.history_len());
+    }
+
+    #[test]
+    fn push_unique_test() {
+        remove_dir_all("savetest/tasklog/push_unique").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/push_unique".to_string());
+        let a_task = task_log.state.add_active_task("task".to_string(), "".to_string(),
+                                                    1.0, 2).unwrap();
+        let entry = TaskLogEntry {
+            timestamp: time::at_utc(time::Timespec::new(1500000000, 0)),
+            action: TaskAction::ScheduleTask(a_task),
+            state: task_log.state.clone()
+        };
+        let hash = task_log.log.push_unique(entry.clone()).unwrap();
+        assert_eq!(Some(hash), task_log.log.head_hash());
+        match task_log.log.push_unique(entry.clone()) {
+            Err(LogError::CustomError(ref msg)) => assert_eq!("duplicate of head", msg),
+            res => panic!("Expected CustomError, got {:?}", res)
+        }
+        assert_eq!(Some(hash), task_log.log.head_hash());
+
+        // push stays permissive
+        assert!(task_log.log.push(entry) != hash);
+        assert_eq!(2, LogIteratorHash::from_log(&task_log.log).count());
+    }
+
+    #[test]
+    fn activate_named_test() {
+        remove_dir_all("savetest/tasklog/activate_named").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/activate_named".to_string());
+        task_log.add_pooled_task("pooled".to_string(), "".to_string(),
+                                 1.0, 0.0, 3, 2).unwrap();
+        let history_len = task_log.history_len();
+        assert_eq!(None, task_log.activate_named("unknown".to_string()).unwrap());
+        assert_eq!(history_len, task_log.history_len());
+
+        let a_task = task_log.activate_named("pooled".to_string()).unwrap().unwrap();
+        let entry = task_log.log.get(task_log.log.head_hash().unwrap()).unwrap();
+        assert_eq!(TaskAction::ActivateTask(vec![a_task]), entry.action);
+    }
+
+    #[test]
+    fn last_hash_test() {
+        remove_dir_all("savetest/tasklog/last_hash").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/last_hash".to_string());
+        assert_eq!(None, task_log.last_hash());
+        task_log.add_active_task("task a".to_string(), "".to_string(), 1.0, 2).unwrap();
+        assert_eq!(task_log.log.head_hash(), task_log.last_hash());
+
+        let p_task = task_log.state.add_pooled_task("pooled".to_string(), "".to_string(),
+                                                    1.0, 0.5, 3, 2).unwrap();
+        let hash = task_log.store_state(TaskAction::PoolTask(p_task)).unwrap();
+        assert_eq!(Some(hash), task_log.log.head_hash());
+        assert_eq!(Some(hash), task_log.last_hash());
+    }
+
+    #[test]
+    fn transaction_test() {
+        remove_dir_all("savetest/tasklog/transaction").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/transaction".to_string());
+        task_log.add_active_task("task a".to_string(), "".to_string(), 1.0, 2).unwrap();
+        let history_len = task_log.history_len();
+
+        let removed = task_log.state.active["task a"].clone();
+        task_log.transaction(|state| {
+            try!(state.add_active_task("task b".to_string(), "".to_string(), 1.0, 2));
+            try!(state.mark_done("task a".to_string()));
+            Ok(())
+        }).unwrap();
+        assert_eq!(history_len + 1, task_log.history_len());
+        let actives = task_log.all_actives().unwrap();
+        assert_eq!(1, actives.len());
+        assert_eq!("task b", actives[0].task.title);
+        let entry = task_log.log.get(task_log.last_hash().unwrap()).unwrap();
+        match entry.action {
+            TaskAction::Batch(ref actions) => {
+                assert_eq!(2, actions.len());
+                assert!(actions.contains(&TaskAction::RemoveActiveTasks(vec![removed])));
+                assert!(actions.contains(&TaskAction::ScheduleTask(actives[0].clone())));
+            },
+            ref action => panic!("Expected Batch action, got {:?}", action)
+        }
+        // Removals are not reported as completions.
+        assert!(task_log.recent_completions(5).is_empty());
+        // The batch can be read back from the log
+        let reloaded = TaskLog::open("savetest/tasklog/transaction".to_string()).unwrap().0;
+        assert_eq!(task_log.state, reloaded.state);
+
+        // A failing transaction changes nothing
+        let state = task_log.state.clone();
+        assert!(task_log.transaction(|state| {
+            try!(state.mark_done("task b".to_string()));
+            try!(state.mark_done("task x".to_string()));
+            Ok(())
+        }).is_err());
+        assert_eq!(state, task_log.state);
+        assert_eq!(history_len + 1, task_log.history_len());
+    }
+
+    #[test]
+    fn reopen_task_test() {
+        remove_dir_all("savetest/tasklog/reopen").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/reopen".to_string());
+        task_log.add_active_task("task a".to_string(), "".to_string(), 1.0, 2).unwrap();
+        let done = task_log.mark_done("task a".to_string()).unwrap();
+        assert_eq!(Some(done.clone()), task_log.reopen_task(done.clone()).unwrap());
+        assert_eq!(vec![done.clone()], task_log.all_actives().unwrap());
+
+        let head = task_log.log.head_hash().unwrap();
+        let entry = task_log.log.get(head).unwrap();
+        assert_eq!(TaskAction::ReopenTask(done.clone()), entry.action);
+
+        // Reopening an active task is not logged.
+        assert_eq!(None, task_log.reopen_task(done).unwrap());
+        assert_eq!(Some(head), task_log.log.head_hash());
+    }
+
+    #[test]
+    fn mark_done_many_test() {
+        remove_dir_all("savetest/tasklog/done_many").ok();
+        let mut task_log = TaskLog::new("savetest/tasklog/done_many".to_string());
+        task_log.add_active_task("task a".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_log.add_active_task("task b".to_string(), "".to_string(), 1.0, 2).unwrap();
+        task_log.add_active_task("task c".to_string(), "".to_string(), 1.0, 2).unwrap();
+        let entries_before = LogIteratorHash::from_log(&task_log.log).count();
+
+        let done = task_log.mark_done_many(&["task a".to_string(), "task x".to_string(),
+                                             "task c".to_string()]).unwrap();
+        assert_eq!(2, done.len());
+        assert_eq!(entries_before + 1, LogIteratorHash::from_log(&task_log.log).count());
+        let entry = task_log.log.get(task_log.log.head_hash().unwrap()).unwrap();
+        assert_eq!(TaskAction::CompleteMany(done), entry.action);
+        assert_eq!(1, task_log.all_actives().unwrap().len());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_test() {
+        use serde_json;
+
+        let mut task_stat = TaskStat::with_ref_tm(time::at_utc(time::Timespec::new(1500000000, 0)));
+        let a_task = task_stat.add_active_task("task a".to_string(), "desc".to_string(),
+                                               1.5, 2).unwrap();
+        let b_task = task_stat.add_active_task("task b".to_string(), "".to_string(),
+                                               1.0, 3).unwrap();
+        let p_task = task_stat.add_pooled_task("pooled".to_string(), "".to_string(),
+                                               1.0, 0.5, 3, 2).unwrap();
+        let p_task_removed = task_stat.add_pooled_task("removed".to_string(), "".to_string(),
+                                                       1.0, 0.0, 3, 2).unwrap();
+        let actions = vec![
+            TaskAction::ScheduleTask(a_task.clone()),
+            TaskAction::PoolTask(p_task.clone()),
+            TaskAction::CompleteTask(a_task.clone()),
+            TaskAction::ActivateTask(vec![a_task.clone(), b_task.clone()]),
+            TaskAction::ReopenTask(b_task.clone()),
+            TaskAction::CompleteMany(vec![b_task.clone()]),
+            TaskAction::Batch(vec![TaskAction::PoolTask(p_task),
+                                   TaskAction::ActivateTask(Vec::new())]),
+            TaskAction::RenameTask { old: "task a".to_string(), new: "task c".to_string() },
+            TaskAction::RemovePooledTasks(vec![p_task_removed]),
+            TaskAction::RemoveActiveTasks(vec![b_task.clone()])
+        ];
+        for action in actions {
+            let json = serde_json::to_string(&action).unwrap();
+            let action_read: TaskAction = serde_json::from_str(&json).unwrap();
+            assert_eq!(action, action_read);
+        }
+
+        let json = serde_json::to_value(&TaskAction::ScheduleTask(a_task)).unwrap();
+        assert_eq!("2017-07-14T00:00:00Z", json["ScheduleTask"]["start"]);
+        assert_eq!("task a", json["ScheduleTask"]["task"]["title"]);
+    }
+}